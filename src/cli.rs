@@ -0,0 +1,295 @@
+use std::{
+    path::PathBuf,
+    sync::{mpsc, Arc},
+    thread,
+    time::Duration,
+};
+
+#[cfg(unix)]
+use serde_json::Value;
+
+use crate::keyboard::{Listener, LoadError, Fallback};
+#[cfg(unix)]
+use crate::keyboard::control::{self, Request};
+
+
+
+/// How often files reloaded while running are checked for errors.
+const REPORT_INTERVAL: Duration = Duration::from_millis(500);
+
+pub const USAGE: &str = "\
+Usage: pvp-macro [--data-dir DIR] [--headless [--profile NAME]]
+       pvp-macro [--data-dir DIR] ctl COMMAND
+
+Options:
+  --data-dir DIR    Keep profiles in DIR, overrides PVP_MACRO_HOME and a
+                    pvp-macro-data folder next to the executable
+  --headless        Run the macro without a window until interrupted
+  --profile NAME    Profile to run, the last used one if not given
+  -h, --help        Print this message
+
+Commands of ctl, sent to the running instance, not on Windows:
+  arm, disarm       Enable or disable the start key
+  start, pause      Run or pause the macro cycle
+  abort             End the macro thread
+  profile NAME      Switch to profile NAME
+  get [FIELD]       Print a setting, like phases.0.count, or all of them
+  set FIELD VALUE   Change a setting, VALUE is json or a plain string
+  status            Print the state of the instance
+  stats             Print clicks sent, cycles run and measured intervals";
+
+
+/// Parsed command line.
+#[derive(Debug, PartialEq)]
+pub struct Args {
+    /// Data directory given with `--data-dir`.
+    pub data_dir: Option<PathBuf>,
+    pub command: Command,
+}
+
+/// What the app was started to do.
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Gui,
+    /// Runs the macro without a window.
+    Headless { profile: Option<String> },
+    /// Sends a request to the running instance.
+    #[cfg(unix)]
+    Ctl(Request),
+    Help,
+}
+
+impl Args {
+    /// Parses command line `args`, without the program name.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` for unknown arguments and missing values.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut data_dir = None;
+        let mut headless = false;
+        let mut profile = None;
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match &arg[..] {
+                "--data-dir" => match args.next() {
+                    Some(dir) => data_dir = Some(PathBuf::from(dir)),
+                    None => return Err(String::from("--data-dir needs a directory")),
+                },
+                "--headless" => headless = true,
+                "--profile" => match args.next() {
+                    Some(name) => profile = Some(name),
+                    None => return Err(String::from("--profile needs a profile name")),
+                },
+                #[cfg(unix)]
+                "ctl" if !headless && profile.is_none() => {
+                    return Ok(Self { data_dir, command: Command::Ctl(parse_ctl(args)?) })
+                },
+                "-h" | "--help" => return Ok(Self { data_dir, command: Command::Help }),
+                _ => return Err(format!("Unknown argument {:?}", arg)),
+            }
+        }
+
+        let command = match (headless, profile) {
+            (true, profile) => Command::Headless { profile },
+            (false, None) => Command::Gui,
+            (false, Some(_)) => return Err(String::from("--profile is only used with --headless")),
+        };
+        Ok(Self { data_dir, command })
+    }
+}
+
+
+#[cfg(unix)]
+fn parse_ctl(args: impl Iterator<Item = String>) -> Result<Request, String> {
+    let args: Vec<_> = args.collect();
+    let args: Vec<_> = args.iter().map(|arg| &arg[..]).collect();
+    let request = match args[..] {
+        ["arm"] => Request::Arm,
+        ["disarm"] => Request::Disarm,
+        ["start"] => Request::Start,
+        ["pause"] => Request::Pause,
+        ["abort"] => Request::Abort,
+        ["profile", name] => Request::Profile { name: String::from(name) },
+        ["get"] => Request::Get { field: String::new() },
+        ["get", field] => Request::Get { field: String::from(field) },
+        ["set", field, value] => Request::Set {
+            field: String::from(field),
+            // key names like Num3 are not valid json
+            value: serde_json::from_str(value).unwrap_or(Value::String(String::from(value))),
+        },
+        ["status"] => Request::Status,
+        ["stats"] => Request::Stats,
+        [] => return Err(String::from("ctl needs a command")),
+        _ => return Err(format!("Unknown ctl command {:?}", args.join(" "))),
+    };
+    Ok(request)
+}
+
+
+/// Sends `request` to the running instance and prints its answer.
+///
+/// # Errors
+///
+/// Returns `Err` if no instance is running or it refused the request.
+#[cfg(unix)]
+pub fn run_ctl(request: &Request) -> Result<(), String> {
+    match control::send(request)? {
+        Value::Null => (),
+        Value::String(text) => println!("{}", text),
+        value => println!("{}", serde_json::to_string_pretty(&value).map_err(|err| err.to_string())?),
+    }
+    Ok(())
+}
+
+
+/// Loads `profile`, arms the macro and listens to the keyboard until the
+/// process is interrupted, then saves changes waiting to be written.
+///
+/// # Errors
+///
+/// Returns `Err` if the profile does not exist, signals cannot be handled
+/// or the keyboard cannot be listened.
+pub fn run_headless(profile: Option<&str>) -> Result<(), String> {
+    let listener = Listener::new();
+    match profile {
+        Some(name) => listener.open_profile(name)?,
+        None => listener.load_settings(),
+    }
+    report(listener.take_load_errors());
+    listener.watch();
+    #[cfg(unix)]
+    let _control = control::serve(&listener)
+        .inspect_err(|err| eprintln!("Control socket unavailable: {}", err))
+        .ok();
+
+    // ends the run, with the error of the input listener if it fails
+    let (end, ended) = mpsc::channel();
+    let interrupt = end.clone();
+    ctrlc::set_handler(move || interrupt.send(Ok(())).unwrap_or(()))
+        .map_err(|err| err.to_string())?;
+
+    // logged as each run starts, to repeat it
+    let seeds = listener.service.seeds();
+    thread::spawn(move || for seed in seeds {
+        eprintln!("Macro started with seed {}, the seed setting set to it repeats the run", seed);
+    });
+
+    let listener2 = Arc::clone(&listener);
+    thread::spawn(move || {
+        let result = listener2.listen()
+            .and_then(|_| Err(String::from("Input listener stopped")))
+            .map_err(|err| format!("Cannot listen to the keyboard: {}", err));
+        end.send(result).unwrap_or(());
+    });
+    listener.start()?;
+    eprintln!("Running profile {:?}, press Ctrl+C to stop", listener.active_profile());
+
+    // files changed while running are reported once reloaded
+    let result = loop {
+        match ended.recv_timeout(REPORT_INTERVAL) {
+            Ok(result) => break result,
+            Err(_) => {
+                report(listener.take_load_errors());
+                report_saves(listener.take_save_errors());
+            },
+        }
+    };
+
+    listener.stop().unwrap_or(());
+    listener.flush_settings();
+    report_saves(listener.take_save_errors());
+    result
+}
+
+fn report_saves(errors: Vec<String>) {
+    for err in errors { eprintln!("{}\n  changes are kept until the next save", err) }
+}
+
+fn report(errors: Vec<LoadError>) {
+    for err in errors {
+        match &err.fallback {
+            Fallback::Defaults { backup: Some(backup) } =>
+                eprintln!("{}\n  using defaults, the file was copied to {}", err, backup.display()),
+            Fallback::Defaults { backup: None } =>
+                eprintln!("{}\n  using defaults, the file could not be backed up and is not saved over", err),
+            Fallback::Kept => eprintln!("{}\n  keeping the values in use", err),
+        }
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Args, String> {
+        Args::parse(args.split_whitespace().map(String::from))
+    }
+
+    fn command(args: &str) -> Result<Command, String> {
+        parse(args).map(|args| args.command)
+    }
+
+    #[test]
+    fn args() {
+        assert_eq!(parse(""), Ok(Args { data_dir: None, command: Command::Gui }));
+        assert_eq!(parse("--data-dir /tmp/a --headless --profile bow"), Ok(Args {
+            data_dir: Some(PathBuf::from("/tmp/a")),
+            command: Command::Headless { profile: Some(String::from("bow")) },
+        }));
+        assert_eq!(command("--profile bow --headless"), Ok(Command::Headless { profile: Some(String::from("bow")) }));
+        // the last value given wins
+        assert_eq!(parse("--data-dir a --data-dir b").map(|args| args.data_dir), Ok(Some(PathBuf::from("b"))));
+        assert_eq!(command("--headless --profile a --profile b"), Ok(Command::Headless { profile: Some(String::from("b")) }));
+        // help stops parsing, even before invalid arguments
+        assert_eq!(command("--headless --help --what"), Ok(Command::Help));
+        assert_eq!(command("-h"), Ok(Command::Help));
+
+        assert_eq!(command("--profile bow"), Err(String::from("--profile is only used with --headless")));
+        assert_eq!(command("--headless --profile"), Err(String::from("--profile needs a profile name")));
+        assert_eq!(command("--data-dir"), Err(String::from("--data-dir needs a directory")));
+        assert_eq!(command("--what"), Err(String::from("Unknown argument \"--what\"")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn ctl() {
+        assert_eq!(parse("--data-dir a ctl status"), Ok(Args { data_dir: Some(PathBuf::from("a")), command: Command::Ctl(Request::Status) }));
+        // arguments after ctl belong to its command
+        assert_eq!(command("ctl profile --headless"), Ok(Command::Ctl(Request::Profile { name: String::from("--headless") })));
+        // ctl is not a command of a headless run
+        assert_eq!(command("--headless ctl status"), Err(String::from("Unknown argument \"ctl\"")));
+        assert_eq!(command("--profile bow ctl status"), Err(String::from("Unknown argument \"ctl\"")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn ctl_commands() {
+        let ctl = |args: &str| parse_ctl(args.split_whitespace().map(String::from));
+        assert_eq!(ctl("arm"), Ok(Request::Arm));
+        assert_eq!(ctl("disarm"), Ok(Request::Disarm));
+        assert_eq!(ctl("start"), Ok(Request::Start));
+        assert_eq!(ctl("pause"), Ok(Request::Pause));
+        assert_eq!(ctl("abort"), Ok(Request::Abort));
+        assert_eq!(ctl("status"), Ok(Request::Status));
+        assert_eq!(ctl("stats"), Ok(Request::Stats));
+        assert_eq!(ctl("profile bow"), Ok(Request::Profile { name: String::from("bow") }));
+        assert_eq!(ctl("get"), Ok(Request::Get { field: String::new() }));
+        assert_eq!(ctl("get phases.0.count"), Ok(Request::Get { field: String::from("phases.0.count") }));
+
+        let set = |field: &str, value: Value| Ok(Request::Set { field: String::from(field), value });
+        assert_eq!(ctl("set phases.0.count 5"), set("phases.0.count", Value::from(5)));
+        assert_eq!(ctl("set seed null"), set("seed", Value::Null));
+        assert_eq!(ctl("set phases.0.distribution {\"Normal\":{\"ratio\":0.1}}"), set("phases.0.distribution", serde_json::json!({"Normal": {"ratio": 0.1}})));
+        // not json, taken as a string
+        assert_eq!(ctl("set phases.0.slot Num3"), set("phases.0.slot", Value::from("Num3")));
+
+        assert_eq!(ctl(""), Err(String::from("ctl needs a command")));
+        assert_eq!(ctl("profile"), Err(String::from("Unknown ctl command \"profile\"")));
+        assert_eq!(ctl("set seed"), Err(String::from("Unknown ctl command \"set seed\"")));
+        assert_eq!(ctl("jump now"), Err(String::from("Unknown ctl command \"jump now\"")));
+    }
+}
@@ -0,0 +1,230 @@
+use std::fmt;
+
+use rdev::{Key, Button};
+use serde::{Serialize, Deserialize};
+
+
+
+/// Modifier keys held down alongside a key.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Modifiers {
+    #[serde(default)]
+    pub ctrl: bool,
+    #[serde(default)]
+    pub shift: bool,
+    #[serde(default)]
+    pub alt: bool,
+}
+
+impl Modifiers {
+    pub const NONE: Self = Self { ctrl: false, shift: false, alt: false };
+
+    /// Returns modifiers set by `key`, or `None` if it is not a modifier key.
+    pub fn of(key: Key) -> Option<Self> {
+        match key {
+            Key::ControlLeft | Key::ControlRight => Some(Self { ctrl: true, ..Self::NONE }),
+            Key::ShiftLeft | Key::ShiftRight => Some(Self { shift: true, ..Self::NONE }),
+            Key::Alt | Key::AltGr => Some(Self { alt: true, ..Self::NONE }),
+            _ => None,
+        }
+    }
+
+    /// Combines modifiers of all `keys` that are modifier keys.
+    pub fn from_keys<'a>(keys: impl IntoIterator<Item = &'a Key>) -> Self {
+        keys.into_iter()
+            .filter_map(|key| Self::of(*key))
+            .fold(Self::NONE, |a, b| a.union(b))
+    }
+
+    pub fn union(self, other: Self) -> Self {
+        Self {
+            ctrl: self.ctrl || other.ctrl,
+            shift: self.shift || other.shift,
+            alt: self.alt || other.alt,
+        }
+    }
+
+    /// Whether every modifier of `self` is also set in `other`.
+    pub fn is_subset(self, other: Self) -> bool {
+        (!self.ctrl || other.ctrl) && (!self.shift || other.shift) && (!self.alt || other.alt)
+    }
+
+    pub fn count(self) -> usize {
+        [self.ctrl, self.shift, self.alt].iter().filter(|set| **set).count()
+    }
+
+    pub fn is_empty(self) -> bool { self.count() == 0 }
+}
+
+
+/// Keyboard key or mouse button.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Input {
+    Key(Key),
+    Button(Button),
+}
+
+impl From<Key> for Input {
+    fn from(key: Key) -> Self { Self::Key(key) }
+}
+
+impl From<Button> for Input {
+    fn from(button: Button) -> Self { Self::Button(button) }
+}
+
+impl fmt::Display for Input {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Key(key) => write!(f, "{:?}", key),
+            Self::Button(Button::Unknown(code)) => write!(f, "Mouse{}", code),
+            Self::Button(button) => write!(f, "Mouse{:?}", button),
+        }
+    }
+}
+
+
+/// A key or mouse button, optionally pressed together with modifiers, that
+/// triggers an action.
+///
+/// Serialized as a bare key when it has no modifiers, so keybindings written
+/// before chords existed still load.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(from = "BindingRepr", into = "BindingRepr")]
+pub struct Binding {
+    pub input: Input,
+    pub modifiers: Modifiers,
+}
+
+impl Binding {
+    pub fn new(input: impl Into<Input>, modifiers: Modifiers) -> Self {
+        Self { input: input.into(), modifiers }
+    }
+
+    /// Whether pressing `input` while `held` modifiers are down triggers the
+    /// binding. Modifiers not part of the binding are ignored, so a bare key
+    /// still works while sprinting or sneaking.
+    pub fn matches(&self, input: Input, held: Modifiers) -> bool {
+        self.input == input && self.modifiers.is_subset(held)
+    }
+
+    /// Returns the key if the binding is not a mouse button.
+    pub fn key(&self) -> Option<Key> {
+        match self.input {
+            Input::Key(key) => Some(key),
+            Input::Button(_) => None,
+        }
+    }
+}
+
+impl From<Key> for Binding {
+    fn from(key: Key) -> Self { Self::new(key, Modifiers::NONE) }
+}
+
+impl From<Button> for Binding {
+    fn from(button: Button) -> Self { Self::new(button, Modifiers::NONE) }
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.modifiers.ctrl { write!(f, "Ctrl+")? }
+        if self.modifiers.shift { write!(f, "Shift+")? }
+        if self.modifiers.alt { write!(f, "Alt+")? }
+        write!(f, "{}", self.input)
+    }
+}
+
+
+// `Key` and `Button` both have an `Unknown` variant, so buttons are tagged.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum BindingRepr {
+    Key(Key),
+    Chord {
+        key: Key,
+        #[serde(default)]
+        modifiers: Modifiers,
+    },
+    Button {
+        button: Button,
+        #[serde(default)]
+        modifiers: Modifiers,
+    },
+}
+
+impl From<BindingRepr> for Binding {
+    fn from(repr: BindingRepr) -> Self {
+        match repr {
+            BindingRepr::Key(key) => Self::from(key),
+            BindingRepr::Chord { key, modifiers } => Self::new(key, modifiers),
+            BindingRepr::Button { button, modifiers } => Self::new(button, modifiers),
+        }
+    }
+}
+
+impl From<Binding> for BindingRepr {
+    fn from(binding: Binding) -> Self {
+        match binding.input {
+            Input::Key(key) if binding.modifiers.is_empty() => Self::Key(key),
+            Input::Key(key) => Self::Chord { key, modifiers: binding.modifiers },
+            Input::Button(button) => Self::Button { button, modifiers: binding.modifiers },
+        }
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    const SHIFT: Modifiers = Modifiers { shift: true, ..Modifiers::NONE };
+    const CTRL_SHIFT: Modifiers = Modifiers { ctrl: true, shift: true, alt: false };
+
+    fn round_trip(binding: Binding, expected: Value) {
+        assert_eq!(serde_json::to_value(binding).unwrap(), expected);
+        assert_eq!(serde_json::from_value::<Binding>(expected).unwrap(), binding);
+    }
+
+    #[test]
+    fn repr() {
+        round_trip(Key::KeyX.into(), json!("KeyX"));
+        round_trip(Binding::new(Key::KeyX, SHIFT), json!({"key": "KeyX", "modifiers": {"ctrl": false, "shift": true, "alt": false}}));
+        round_trip(Button::Unknown(8).into(), json!({"button": {"Unknown": 8}, "modifiers": {"ctrl": false, "shift": false, "alt": false}}));
+        round_trip(Binding::new(Button::Middle, CTRL_SHIFT), json!({"button": "Middle", "modifiers": {"ctrl": true, "shift": true, "alt": false}}));
+
+        // written by hand, with modifiers left out
+        let parse = |value| serde_json::from_value::<Binding>(value).unwrap();
+        assert_eq!(parse(json!({"key": "KeyX"})), Binding::from(Key::KeyX));
+        assert_eq!(parse(json!({"key": "KeyX", "modifiers": {"shift": true}})), Binding::new(Key::KeyX, SHIFT));
+        assert_eq!(parse(json!({"button": {"Unknown": 9}})), Binding::from(Button::Unknown(9)));
+        assert!(serde_json::from_value::<Binding>(json!({"modifiers": {}})).is_err());
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(Binding::from(Key::KeyX).to_string(), "KeyX");
+        assert_eq!(Binding::new(Key::Num1, CTRL_SHIFT).to_string(), "Ctrl+Shift+Num1");
+        assert_eq!(Binding::new(Button::Unknown(8), Modifiers { alt: true, ..Modifiers::NONE }).to_string(), "Alt+Mouse8");
+        assert_eq!(Binding::from(Button::Middle).to_string(), "MouseMiddle");
+    }
+
+    #[test]
+    fn matches() {
+        let bare = Binding::from(Key::KeyX);
+        let chord = Binding::new(Key::KeyX, SHIFT);
+        let x = Input::Key(Key::KeyX);
+
+        // extra modifiers held are ignored, missing ones are not
+        assert!(bare.matches(x, Modifiers::NONE) && bare.matches(x, SHIFT) && bare.matches(x, CTRL_SHIFT));
+        assert!(!chord.matches(x, Modifiers::NONE) && !chord.matches(x, Modifiers { ctrl: true, ..Modifiers::NONE }));
+        assert!(chord.matches(x, SHIFT) && chord.matches(x, CTRL_SHIFT));
+        assert!(!chord.matches(Input::Key(Key::KeyC), SHIFT));
+        assert!(!Binding::from(Button::Unknown(8)).matches(Input::Button(Button::Unknown(9)), Modifiers::NONE));
+
+        assert!(Modifiers::NONE.is_subset(SHIFT) && SHIFT.is_subset(CTRL_SHIFT) && !CTRL_SHIFT.is_subset(SHIFT));
+        assert_eq!(Modifiers::from_keys(&[Key::ShiftRight, Key::KeyA, Key::ControlLeft]), CTRL_SHIFT);
+        assert_eq!(Modifiers::of(Key::KeyA), None);
+        assert_eq!(CTRL_SHIFT.count(), 2);
+    }
+}
@@ -0,0 +1,227 @@
+use std::{
+    sync::{Arc, Condvar, Mutex},
+    time::{Duration, Instant},
+};
+
+
+
+/// Time source of the macro and the worker sending its events.
+///
+/// Threads block through the clock instead of on their own condvars, so a
+/// [`VirtualClock`] knows when all of them are waiting and time can move on.
+pub trait Clock: Send + Sync + 'static {
+    /// Time since the clock was created.
+    fn now(&self) -> Duration;
+
+    /// Blocks until `ready` returns `true` or `deadline` passes, returning
+    /// whether it became ready. `ready` is checked again on every `notify`.
+    fn wait_until(&self, deadline: Option<Duration>, ready: &mut dyn FnMut() -> bool) -> bool;
+
+    /// Wakes waiting threads to check their condition, to be called after
+    /// changing what they wait for.
+    fn notify(&self);
+
+    /// Counts a thread that waits through the clock, see [`Participant`].
+    fn enter(&self) {}
+
+    fn exit(&self) {}
+
+    fn sleep(&self, duration: Duration) {
+        self.wait_until(Some(self.now() + duration), &mut || false);
+    }
+}
+
+
+/// Counts a thread as running on `clock` until dropped. Created before the
+/// thread is spawned and moved into it, so a [`VirtualClock`] never moves on
+/// before the thread got to wait.
+pub struct Participant {
+    clock: Arc<dyn Clock>,
+}
+
+impl Participant {
+    pub fn new(clock: &Arc<dyn Clock>) -> Self {
+        clock.enter();
+        Self { clock: Arc::clone(clock) }
+    }
+}
+
+impl Drop for Participant {
+    fn drop(&mut self) { self.clock.exit() }
+}
+
+
+/// Wall clock time, on a monotonic clock.
+pub struct RealClock {
+    epoch: Instant,
+    lock: Mutex<()>,
+    cvar: Condvar,
+}
+
+impl RealClock {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self { epoch: Instant::now(), lock: Mutex::new(()), cvar: Condvar::new() })
+    }
+}
+
+impl Clock for RealClock {
+    fn now(&self) -> Duration { self.epoch.elapsed() }
+
+    fn wait_until(&self, deadline: Option<Duration>, ready: &mut dyn FnMut() -> bool) -> bool {
+        let mut lock = self.lock.lock().unwrap();
+        loop {
+            if ready() { return true }
+            lock = match deadline {
+                Some(deadline) => {
+                    let now = self.now();
+                    if now >= deadline { return false }
+                    self.cvar.wait_timeout(lock, deadline - now).unwrap().0
+                },
+                None => self.cvar.wait(lock).unwrap(),
+            };
+        }
+    }
+
+    fn notify(&self) {
+        let _lock = self.lock.lock().unwrap();
+        self.cvar.notify_all();
+    }
+}
+
+
+/// Time that only moves when `advance` is called, for tests. Waits end in
+/// deadline order at the exact time they are due, however long the threads
+/// take to get there.
+#[derive(Default)]
+pub struct VirtualClock {
+    state: Mutex<VirtualState>,
+    /// Wakes waiting threads.
+    cvar: Condvar,
+    /// Wakes `advance` once every thread is waiting.
+    idle: Condvar,
+}
+
+#[derive(Default)]
+struct VirtualState {
+    now: Duration,
+    /// Incremented on every notification and time step.
+    epoch: u64,
+    /// Participants not waiting.
+    running: usize,
+    /// Key, deadline and last seen epoch of waiting threads.
+    waiting: Vec<(u64, Option<Duration>, u64)>,
+    next_key: u64,
+}
+
+impl VirtualState {
+    /// Every participant waits and has checked its condition since the
+    /// last change.
+    fn is_idle(&self) -> bool {
+        self.running == 0 && self.waiting.iter().all(|(_, _, seen)| *seen == self.epoch)
+    }
+}
+
+impl VirtualClock {
+    pub fn new() -> Arc<Self> { Arc::new(Self::default()) }
+
+    /// Moves time forward by `duration`, stopping at each deadline on the
+    /// way until the threads due there are waiting again.
+    pub fn advance(&self, duration: Duration) {
+        let mut state = self.state.lock().unwrap();
+        let target = state.now + duration;
+        loop {
+            state = self.idle.wait_while(state, |state| !state.is_idle()).unwrap();
+            let next = state.waiting.iter()
+                .filter_map(|(_, deadline, _)| *deadline)
+                .filter(|deadline| *deadline > state.now && *deadline <= target)
+                .min();
+            state.now = next.unwrap_or(target);
+            state.epoch += 1;
+            self.cvar.notify_all();
+            if next.is_none() { break }
+        }
+        drop(self.idle.wait_while(state, |state| !state.is_idle()).unwrap());
+    }
+
+    /// Waits until every participant is waiting, without moving time.
+    pub fn settle(&self) {
+        self.advance(Duration::ZERO);
+    }
+}
+
+impl Clock for VirtualClock {
+    fn now(&self) -> Duration { self.state.lock().unwrap().now }
+
+    fn wait_until(&self, deadline: Option<Duration>, ready: &mut dyn FnMut() -> bool) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let key = state.next_key;
+        state.next_key += 1;
+        // only participants may wait, others would keep time from moving
+        let Some(running) = state.running.checked_sub(1) else {
+            drop(state);
+            panic!("VirtualClock waited on by a thread that is not a Participant");
+        };
+        state.running = running;
+
+        let result = loop {
+            if ready() { break true }
+            if deadline.is_some_and(|deadline| state.now >= deadline) { break false }
+            let epoch = state.epoch;
+            match state.waiting.iter_mut().find(|(waiting, _, _)| *waiting == key) {
+                Some(waiting) => waiting.2 = epoch,
+                None => state.waiting.push((key, deadline, epoch)),
+            }
+            self.idle.notify_all();
+            state = self.cvar.wait(state).unwrap();
+        };
+
+        state.waiting.retain(|(waiting, _, _)| *waiting != key);
+        state.running += 1;
+        result
+    }
+
+    fn notify(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.epoch += 1;
+        self.cvar.notify_all();
+    }
+
+    fn enter(&self) {
+        self.state.lock().unwrap().running += 1;
+    }
+
+    fn exit(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.running = state.running.saturating_sub(1);
+        self.idle.notify_all();
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn participants() {
+        let clock = VirtualClock::new();
+        let dyn_clock: Arc<dyn Clock> = clock.clone();
+        let participant = Participant::new(&dyn_clock);
+        let sleeper = thread::spawn(move || {
+            let _participant = participant;
+            dyn_clock.sleep(Duration::from_millis(50));
+            dyn_clock.now()
+        });
+        clock.advance(Duration::from_millis(80));
+        assert_eq!(sleeper.join().unwrap(), Duration::from_millis(50));
+        assert_eq!(clock.now(), Duration::from_millis(80));
+    }
+
+    #[test]
+    #[should_panic(expected = "not a Participant")]
+    fn waiting_without_participant() {
+        VirtualClock::new().sleep(Duration::from_millis(10));
+    }
+}
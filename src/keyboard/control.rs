@@ -0,0 +1,228 @@
+use std::{
+    fs, io::{self, BufRead, BufReader, Write},
+    path::PathBuf,
+    sync::Arc,
+    thread,
+    time::Duration,
+    os::unix::{
+        fs::PermissionsExt,
+        net::{UnixListener, UnixStream},
+    },
+};
+
+use serde::{Serialize, Deserialize};
+use serde_json::{json, Value};
+
+use super::Listener;
+
+
+
+/// Wait for the answer of a running instance.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+
+/// Socket a running instance is controlled through.
+pub fn socket_path() -> io::Result<PathBuf> {
+    Ok(crate::data_dir().map_err(io::Error::other)?.join("control.sock"))
+}
+
+
+/// Command sent to a running instance, one json object per line like
+/// `{"command":"set","field":"return_delay_millis","value":150}`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Request {
+    /// Lets the start key run the macro, see [`Listener::start`].
+    Arm,
+    Disarm,
+    /// Runs the macro cycle right away.
+    Start,
+    Pause,
+    /// Ends the macro thread, it does not run again until restarted.
+    Abort,
+    Profile { name: String },
+    /// `field` is a dot separated path like `phases.0.count`, every setting
+    /// if empty.
+    Get { #[serde(default)] field: String },
+    Set { field: String, value: Value },
+    Status,
+    /// Counters of the macro, see [`Listener::stats`].
+    Stats,
+}
+
+/// Answer to a [`Request`], `{"ok":value}` or `{"error":message}`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Response {
+    Ok(Value),
+    Error(String),
+}
+
+impl From<Result<Value, String>> for Response {
+    fn from(result: Result<Value, String>) -> Self {
+        match result {
+            Ok(value) => Self::Ok(value),
+            Err(err) => Self::Error(err),
+        }
+    }
+}
+
+
+/// Carries out `request` on `listener`.
+///
+/// # Errors
+///
+/// Returns `Err` if the command is not possible in the current state.
+pub fn handle(listener: &Listener, request: Request) -> Result<Value, String> {
+    match request {
+        Request::Arm => listener.start().map(|_| Value::Null),
+        Request::Disarm => listener.stop().map(|_| Value::Null),
+        Request::Start => listener.service.start().map(|_| Value::Null),
+        Request::Pause => listener.service.pause().map(|_| Value::Null),
+        Request::Abort => listener.service.abort().map(|_| Value::Null),
+        Request::Profile { name } => listener.set_active_profile(&name).map(|_| Value::Null),
+        Request::Get { field } => listener.get_setting(&field),
+        Request::Set { field, value } => listener.set_setting(&field, value).map(|_| Value::Null),
+        Request::Status => Ok(json!({
+            "armed": listener.is_running(),
+            "running": listener.service.is_running(),
+            "listening": listener.is_listening(),
+            "profile": listener.active_profile(),
+            "profiles": listener.profiles(),
+        })),
+        Request::Stats => serde_json::to_value(listener.stats()).map_err(|err| err.to_string()),
+    }
+}
+
+/// Answers a line of the protocol.
+fn respond(listener: &Listener, line: &str) -> Response {
+    match serde_json::from_str(line) {
+        Ok(request) => handle(listener, request).into(),
+        Err(err) => Response::Error(format!("Invalid request: {}", err)),
+    }
+}
+
+
+/// Removes the socket once dropped, so clients do not reach a dead instance.
+pub struct Server {
+    path: PathBuf,
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        fs::remove_file(&self.path).unwrap_or(());
+    }
+}
+
+/// Starts a thread answering requests on `socket_path`, only the current
+/// user may connect to it.
+///
+/// # Errors
+///
+/// Returns `Err` if another instance is listening already or the socket
+/// cannot be created.
+pub fn serve(listener: &Arc<Listener>) -> io::Result<Server> {
+    let path = socket_path()?;
+    if path.exists() {
+        if UnixStream::connect(&path).is_ok() {
+            return Err(io::Error::new(io::ErrorKind::AddrInUse, "Another instance is running"))
+        }
+        // left behind by an instance that did not exit cleanly
+        fs::remove_file(&path)?;
+    }
+    let socket = UnixListener::bind(&path)?;
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+
+    let listener = Arc::clone(listener);
+    thread::spawn(move || {
+        for stream in socket.incoming().filter_map(Result::ok) {
+            let listener = Arc::clone(&listener);
+            thread::spawn(move || serve_client(&listener, stream).unwrap_or(()));
+        }
+    });
+    Ok(Server { path })
+}
+
+fn serve_client(listener: &Listener, stream: UnixStream) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() { continue }
+        let response = serde_json::to_string(&respond(listener, &line))?;
+        writeln!(writer, "{}", response)?;
+    }
+    Ok(())
+}
+
+/// Sends `request` to the running instance and returns its answer.
+///
+/// # Errors
+///
+/// Returns `Err` if no instance is running or it refused the request.
+pub fn send(request: &Request) -> Result<Value, String> {
+    let stream = socket_path().and_then(UnixStream::connect)
+        .map_err(|err| format!("No running instance found: {}", err))?;
+    stream.set_read_timeout(Some(TIMEOUT)).map_err(|err| err.to_string())?;
+
+    let mut writer = stream.try_clone().map_err(|err| err.to_string())?;
+    let request = serde_json::to_string(request).map_err(|err| err.to_string())?;
+    writeln!(writer, "{}", request).map_err(|err| err.to_string())?;
+
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line).map_err(|err| err.to_string())?;
+    match serde_json::from_str(&line).map_err(|err| format!("Invalid response: {}", err))? {
+        Response::Ok(value) => Ok(value),
+        Response::Error(err) => Err(err),
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyboard::{RecordingSink, VirtualClock};
+
+    #[test]
+    fn json_shape() {
+        let request = Request::Set { field: String::from("return_delay_millis"), value: Value::from(150) };
+        assert_eq!(serde_json::to_value(&request).unwrap(), json!({"command": "set", "field": "return_delay_millis", "value": 150}));
+        assert_eq!(serde_json::to_value(Request::Profile { name: String::from("bow") }).unwrap(), json!({"command": "profile", "name": "bow"}));
+        assert_eq!(serde_json::to_value(Request::Arm).unwrap(), json!({"command": "arm"}));
+
+        let parse = |line| serde_json::from_str::<Request>(line).map_err(|err| err.to_string());
+        assert_eq!(parse(r#"{"command":"get"}"#), Ok(Request::Get { field: String::new() }));
+        assert_eq!(parse(r#"{"command":"get","field":"seed"}"#), Ok(Request::Get { field: String::from("seed") }));
+        assert_eq!(parse(r#"{"command":"stats"}"#), Ok(Request::Stats));
+        assert!(parse(r#"{"command":"set","field":"seed"}"#).is_err());
+        assert!(parse(r#"{"command":"jump"}"#).is_err());
+
+        assert_eq!(serde_json::to_value(Response::Ok(Value::Null)).unwrap(), json!({"ok": null}));
+        assert_eq!(serde_json::to_value(Response::Error(String::from("no"))).unwrap(), json!({"error": "no"}));
+    }
+
+    #[test]
+    fn requests() {
+        let _dir = crate::TestDataDir::new("control");
+        let clock = VirtualClock::new();
+        let listener = Listener::with_clock(RecordingSink::with_clock(clock.clone()), clock.clone());
+        let status = |listener: &Listener| handle(listener, Request::Status).unwrap();
+
+        assert_eq!(status(&listener)["armed"], json!(false));
+        assert_eq!(handle(&listener, Request::Arm), Ok(Value::Null));
+        assert_eq!(status(&listener)["armed"], json!(true));
+        assert_eq!(handle(&listener, Request::Disarm), Ok(Value::Null));
+        assert_eq!(status(&listener)["armed"], json!(false));
+
+        let set = Request::Set { field: String::from("phases.1.count"), value: Value::from(2) };
+        assert_eq!(handle(&listener, set), Ok(Value::Null));
+        assert_eq!(handle(&listener, Request::Get { field: String::from("phases.1.count") }), Ok(Value::from(2)));
+        assert!(handle(&listener, Request::Get { field: String::new() }).unwrap().is_object());
+        assert_eq!(handle(&listener, Request::Profile { name: String::from("missing") }), Err(String::from("Profile \"missing\" does not exist")));
+        assert!(handle(&listener, Request::Stats).unwrap().is_object());
+
+        assert_eq!(respond(&listener, r#"{"command":"get","field":"phases.1.count"}"#), Response::Ok(Value::from(2)));
+        assert_eq!(respond(&listener, r#"{"command":"get","field":"speed"}"#), Response::Error(String::from("Unknown setting \"speed\"")));
+        assert!(matches!(respond(&listener, "get"), Response::Error(err) if err.starts_with("Invalid request: ")));
+    }
+}
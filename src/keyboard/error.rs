@@ -0,0 +1,50 @@
+use std::{fmt, path::PathBuf};
+
+
+
+/// A settings or keybindings file that could not be read, defaults are used
+/// in its place.
+#[derive(Debug, Clone)]
+pub struct LoadError {
+    pub file: PathBuf,
+    /// Path of the offending field, like `custom[2].slot`, if known.
+    pub field: Option<String>,
+    /// 1-based line and column of the error, unknown for migrated files.
+    pub position: Option<(usize, usize)>,
+    pub message: String,
+    pub fallback: Fallback,
+}
+
+
+/// What is used in place of an unreadable file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fallback {
+    /// Defaults, the file is copied to `backup` unless that failed.
+    Defaults { backup: Option<PathBuf> },
+    /// Values in use, as the file was changed by another program while
+    /// running.
+    Kept,
+}
+
+impl LoadError {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            file: PathBuf::new(),
+            field: None,
+            position: None,
+            message: message.into(),
+            fallback: Fallback::Defaults { backup: None },
+        }
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.file.display())?;
+        if let Some((line, column)) = self.position { write!(f, ":{}:{}", line, column)? }
+        if let Some(field) = &self.field { write!(f, " ({})", field)? }
+        write!(f, ": {}", self.message)
+    }
+}
+
+impl std::error::Error for LoadError { }
@@ -0,0 +1,290 @@
+use std::path::Path;
+
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use toml_edit::{DocumentMut, Item, Table};
+
+use super::{error::LoadError, schema};
+
+
+
+/// File format of settings and keybindings, picked by extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    /// Keeps comments and layout of hand edited files when written over.
+    Toml,
+}
+
+impl Format {
+    pub const ALL: [Self; 2] = [Self::Json, Self::Toml];
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Toml => "toml",
+        }
+    }
+
+    /// Format of `path` by its extension, `None` if it is not known.
+    pub fn of(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        Self::ALL.into_iter().find(|format| format.extension() == extension)
+    }
+
+    /// Name of file `stem` in this format, like `settings.toml`.
+    pub fn file_name(self, stem: &str) -> String {
+        format!("{}.{}", stem, self.extension())
+    }
+
+    /// Parses `text` without checking what it contains, into a value that
+    /// can be migrated.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` with the position of the error if `text` is not valid.
+    pub fn parse(self, text: &str) -> Result<Value, LoadError> {
+        match self {
+            Self::Json => serde_json::from_str(text).map_err(|err| json_error(None, err)),
+            Self::Toml => toml::from_str(text).map_err(|err| toml_error(text, None, err)),
+        }
+    }
+
+    /// Deserializes `text`, locating errors by field and position.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if `text` is not valid or does not match `T`.
+    pub fn deserialize<T: DeserializeOwned>(self, text: &str) -> Result<T, LoadError> {
+        match self {
+            Self::Json => {
+                let mut deserializer = serde_json::Deserializer::from_str(text);
+                serde_path_to_error::deserialize(&mut deserializer)
+                    .map_err(|err| json_error(field(&err), err.into_inner()))
+            },
+            Self::Toml => {
+                serde_path_to_error::deserialize(toml::Deserializer::new(text))
+                    .map_err(|err| toml_error(text, field(&err), err.into_inner()))
+            },
+        }
+    }
+
+    /// Serializes `value` stamped with the schema version.
+    ///
+    /// The values of a TOML file are written into its `previous` text, so
+    /// comments and formatting survive saves from the app.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if `value` cannot be represented in this format.
+    pub fn serialize<T: Serialize>(self, value: &T, previous: Option<&str>) -> Result<String, String> {
+        match self {
+            Self::Json => {
+                let mut value = serde_json::to_value(value).map_err(|err| err.to_string())?;
+                schema::stamp(&mut value);
+                serde_json::to_string_pretty(&value).map(|text| text + "\n").map_err(|err| err.to_string())
+            },
+            Self::Toml => {
+                let text = toml::to_string_pretty(value).map_err(|err| err.to_string())?;
+                // a plain value has to come before tables
+                let text = format!("{} = {}\n\n{}", schema::VERSION_FIELD, schema::VERSION, text);
+                let new: DocumentMut = text.parse().map_err(|err: toml_edit::TomlError| err.to_string())?;
+                match previous.and_then(|previous| previous.parse::<DocumentMut>().ok()) {
+                    Some(mut document) => {
+                        merge_table(document.as_table_mut(), new.as_table());
+                        Ok(document.to_string())
+                    },
+                    None => Ok(new.to_string()),
+                }
+            },
+        }
+    }
+}
+
+
+/// Deserializes a value changed by migrations, whose errors cannot be
+/// located in the file anymore.
+///
+/// # Errors
+///
+/// Returns `Err` with the field if `value` does not match `T`.
+pub fn from_migrated<T: DeserializeOwned>(value: Value) -> Result<T, LoadError> {
+    serde_path_to_error::deserialize(value).map_err(|err| {
+        let field = field(&err);
+        LoadError { position: None, ..json_error(field, err.into_inner()) }
+    })
+}
+
+fn field<E>(err: &serde_path_to_error::Error<E>) -> Option<String> {
+    let path = err.path().to_string();
+    if path == "." { None } else { Some(path) }
+}
+
+fn json_error(field: Option<String>, err: serde_json::Error) -> LoadError {
+    LoadError {
+        field,
+        position: Some((err.line(), err.column())),
+        ..LoadError::new(short_message(&err.to_string()))
+    }
+}
+
+fn toml_error(text: &str, field: Option<String>, err: toml::de::Error) -> LoadError {
+    LoadError {
+        field,
+        position: err.span().map(|span| position(text, span.start)),
+        ..LoadError::new(short_message(err.message().trim()))
+    }
+}
+
+/// `message` without the " at line x column y" suffix and the list of every
+/// key name after unknown variants.
+fn short_message(message: &str) -> String {
+    let end = [", expected one of", " at line "]
+        .iter()
+        .filter_map(|suffix| message.find(suffix))
+        .min()
+        .unwrap_or(message.len());
+    String::from(&message[..end])
+}
+
+/// 1-based line and column of byte `offset` in `text`.
+fn position(text: &str, offset: usize) -> (usize, usize) {
+    let before = text.get(..offset).unwrap_or(text);
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (before.matches('\n').count() + 1, before[line_start..].chars().count() + 1)
+}
+
+
+/// Writes entries of `new` into `old`. Entries both have keep their
+/// comments, missing ones are removed and new ones are appended.
+fn merge_table(old: &mut Table, new: &Table) {
+    old.retain(|key, _| new.contains_key(key));
+    for (key, item) in new.iter() {
+        match old.get_mut(key) {
+            Some(old) => merge_item(old, item),
+            None => { old.insert(key, item.clone()); },
+        }
+    }
+}
+
+fn merge_item(old: &mut Item, new: &Item) {
+    match (old, new) {
+        (Item::Table(old), Item::Table(new)) => merge_table(old, new),
+        (Item::ArrayOfTables(old), Item::ArrayOfTables(new)) => {
+            while old.len() > new.len() { old.remove(old.len() - 1) }
+            for (i, table) in new.iter().enumerate() {
+                match old.get_mut(i) {
+                    Some(old) => merge_table(old, table),
+                    None => old.push(table.clone()),
+                }
+            }
+        },
+        (Item::Value(old), Item::Value(new)) => {
+            let decor = old.decor().clone();
+            *old = new.clone();
+            *old.decor_mut() = decor;
+        },
+        (old, new) => *old = new.clone(),
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rdev::{Button, Key};
+    use crate::keyboard::{
+        minecraft::{Action, Custom, KeyBindings, Step},
+        run::Settings,
+        Binding, Modifiers, SaveJson,
+    };
+
+    fn keybindings() -> KeyBindings {
+        let mut keybindings = KeyBindings::default();
+        keybindings.start = Binding::new(Key::KeyR, Modifiers { ctrl: true, ..Modifiers::NONE });
+        keybindings.custom.push(Custom::new(Button::Unknown(8), Key::Num7, Action::Hold { millis: 1_200 }));
+        keybindings.custom.push(Custom::new(Key::KeyB, Key::Num8, Action::Sequence(vec![
+            Step::click(Button::Right),
+            Step { slot: Some(Key::Num9), button: Button::Left, hold_millis: 50, delay_millis: 20 },
+        ])));
+        keybindings.profile_select.push((Key::F1.into(), String::from("bow")));
+        keybindings
+    }
+
+    /// Serializes `value` into `previous` and loads it back.
+    fn round_trip<T: SaveJson>(value: &T, format: Format, previous: Option<&str>) -> (String, T) {
+        let text = format.serialize(value, previous).unwrap();
+        let loaded = T::from_text(&text, format).unwrap();
+        (text, loaded)
+    }
+
+    fn same<T: Serialize>(a: &T, b: &T) -> bool {
+        serde_json::to_value(a).unwrap() == serde_json::to_value(b).unwrap()
+    }
+
+    #[test]
+    fn round_trips() {
+        let mut settings = Settings { seed: Some(42), ..Settings::default() };
+        settings.phases[1].count = 9;
+        let keybindings = keybindings();
+
+        for format in Format::ALL {
+            let (text, loaded) = round_trip(&settings, format, None);
+            assert!(same(&settings, &loaded), "{:?}:\n{}", format, text);
+            let (text, loaded) = round_trip(&keybindings, format, None);
+            assert!(same(&keybindings, &loaded), "{:?}:\n{}", format, text);
+        }
+    }
+
+    #[test]
+    fn comments_kept() {
+        let mut settings = Settings::default();
+        let previous = Format::Toml.serialize(&settings, None).unwrap()
+            .replace("return_delay_millis = 100", "# after custom uses\nreturn_delay_millis   =   100 # in ms")
+            .replace("count = 5", "count = 5 # rod throws");
+        settings.return_delay_millis = 150;
+
+        let (text, loaded) = round_trip(&settings, Format::Toml, Some(&previous));
+        assert!(same(&settings, &loaded));
+        assert!(text.contains("# after custom uses\nreturn_delay_millis   =   150 # in ms\n"), "{}", text);
+        assert!(text.contains("count = 5 # rod throws\n"), "{}", text);
+
+        // nothing changed, nothing rewritten
+        assert_eq!(Format::Toml.serialize(&loaded, Some(&text)).unwrap(), text);
+    }
+
+    #[test]
+    fn arrays_of_tables() {
+        let mut settings = Settings::default();
+        let previous = Format::Toml.serialize(&settings, None).unwrap()
+            .replace("count = 7", "count = 7 # sword hits");
+
+        // a removed phase takes its keys along, the one left keeps comments
+        settings.phases.pop();
+        let (text, loaded) = round_trip(&settings, Format::Toml, Some(&previous));
+        assert!(same(&settings, &loaded));
+        assert_eq!(text.matches("[[phases]]").count(), 1);
+        assert!(text.contains("count = 7 # sword hits\n") && !text.contains("Num2"), "{}", text);
+
+        // an added phase is appended after it
+        settings.phases.push(crate::keyboard::run::Phase::fishing_rod());
+        settings.phases.push(crate::keyboard::run::Phase::sword());
+        let (text, loaded) = round_trip(&settings, Format::Toml, Some(&text));
+        assert!(same(&settings, &loaded));
+        assert_eq!(text.matches("[[phases]]").count(), 3);
+        assert!(text.contains("count = 7 # sword hits\n"), "{}", text);
+
+        // keys of a custom binding come and go with its action
+        let mut keybindings = keybindings();
+        let previous = Format::Toml.serialize(&keybindings, None).unwrap()
+            .replace("hotkey = \"KeyX\"", "hotkey = \"KeyX\" # pearl");
+        keybindings.custom[0].action = Action::Hold { millis: 300 };
+        keybindings.custom[5].action = Action::Punch;
+        keybindings.custom.remove(4);
+        let (text, loaded) = round_trip(&keybindings, Format::Toml, Some(&previous));
+        assert!(same(&keybindings, &loaded), "{}", text);
+        assert_eq!(text.matches("[[custom]]").count(), 5);
+        assert!(text.contains("hotkey = \"KeyX\" # pearl\n") && !text.contains("Sequence"), "{}", text);
+    }
+}
@@ -1,104 +1,337 @@
 use std::{
     fs, io, 
+    path::PathBuf,
     sync::{
         Arc, Mutex,
         Condvar
-    }
+    },
+    thread, time::Duration,
 };
 
-use super::{run, minecraft};
+use super::{
+    run, minecraft, profile,
+    binding::{Binding, Input, Modifiers},
+    clock::{Clock, Participant},
+    error::{LoadError, Fallback},
+    format::{self, Format},
+    share::SharedProfile,
+    saver::Saver,
+    sink::InputSink,
+    source::{EventSource, RdevSource},
+    stats::Snapshot,
+    SaveJson,
+};
 
-use crate::data_dir;
 use rdev::{
-    listen,
     Event, EventType,
-    Key
+    Key, Button,
 };
+use serde_json::Value;
+
+/// Wait after the last change before settings are written.
+const SAVE_DELAY: Duration = Duration::from_millis(500);
+
+/// Wait between checks of the active profile files for outside changes.
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
 
 /// Listens keyboard and manages macro.
+///
+/// When both are held, the settings are locked before the keybindings. The
+/// keybindings are never held while calling into the service, as it locks
+/// the settings.
 pub struct Listener {
     listening: Mutex<bool>,
     running: Mutex<bool>,
+    profile: Mutex<String>,
+    start_held: Mutex<bool>,
+    /// Input of the profile hotkey held down, its key repeats are ignored.
+    profile_held: Mutex<Option<Input>>,
+    /// Incremented on each timed activation, so stale countdowns are ignored.
+    activation_id: Arc<Mutex<u64>>,
     pub service: Arc<run::MacroService>,
     pub minecraft: Arc<minecraft::Minecraft>,
-    event_key: Arc<(Mutex<bool>, Mutex<Option<Key>>, Condvar)>
+    event_key: Arc<(Mutex<bool>, Mutex<Option<Binding>>, Condvar)>,
+    /// Modifier pressed while capturing, bound alone if released first.
+    capture_modifier: Mutex<Option<Key>>,
+    /// Modifier keys currently held down.
+    modifiers: Mutex<Vec<Key>>,
+    load_errors: Mutex<Vec<LoadError>>,
+    /// Profiles that could not be written, see `take_save_errors`.
+    save_errors: Arc<Mutex<Vec<String>>>,
+    /// Unreadable files that could not be backed up, left out of saves
+    /// until a backup succeeds.
+    unsaved: Arc<Mutex<Vec<PathBuf>>>,
+    saver: Saver,
+    generation: Mutex<u64>,
+    /// Held while a profile hotkey switches, so hotkeys pressed in a row
+    /// switch in turn.
+    switching: Mutex<()>,
 }
 
 impl Listener {
     pub fn new() -> Arc<Self> {
-        let minecraft = minecraft::Minecraft::new();
+        Self::with_minecraft(minecraft::Minecraft::new())
+    }
+
+    /// Creates a listener whose macro emits its events into `sink`.
+    pub fn with_sink(sink: impl InputSink) -> Arc<Self> {
+        Self::with_minecraft(minecraft::Minecraft::with_sink(sink))
+    }
+
+    /// Creates a listener whose macro emits its events into `sink`, timed
+    /// on `clock`.
+    pub fn with_clock(sink: impl InputSink, clock: Arc<dyn Clock>) -> Arc<Self> {
+        Self::with_minecraft(minecraft::Minecraft::with_clock(sink, clock))
+    }
+
+    fn with_minecraft(minecraft: Arc<minecraft::Minecraft>) -> Arc<Self> {
         let service = run::MacroService::new(Arc::clone(&minecraft));
         Arc::clone(&service).init().unwrap();
+        let unsaved = Arc::new(Mutex::new(Vec::new()));
+        let save_errors = Arc::new(Mutex::new(Vec::new()));
+        let saver = {
+            let service = Arc::clone(&service);
+            let minecraft = Arc::clone(&minecraft);
+            let unsaved = Arc::clone(&unsaved);
+            let save_errors = Arc::clone(&save_errors);
+            Saver::new(SAVE_DELAY, move |name| {
+                keep_save_error(&save_errors, name, write_profile(&service, &minecraft, &unsaved, name));
+            })
+        };
         Arc::new(
             Self {
                 listening: Mutex::new(false),
                 running: Mutex::new(false),
+                profile: Mutex::new(String::from(profile::DEFAULT_PROFILE)),
+                start_held: Mutex::new(false),
+                profile_held: Mutex::new(None),
+                activation_id: Arc::new(Mutex::new(0)),
                 minecraft,
                 service,
-                event_key: Arc::new((Mutex::new(false), Mutex::new(None), Condvar::new()))
+                event_key: Arc::new((Mutex::new(false), Mutex::new(None), Condvar::new())),
+                capture_modifier: Mutex::new(None),
+                modifiers: Mutex::new(Vec::new()),
+                load_errors: Mutex::new(Vec::new()),
+                save_errors,
+                unsaved,
+                saver,
+                generation: Mutex::new(0),
+                switching: Mutex::new(()),
             }
         )
     }
 
     /// Runs keyboard listener.
-    pub fn listen(self: &Arc<Self>) {
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if global input cannot be listened, e.g. without a
+    /// display or the permission to.
+    pub fn listen(self: &Arc<Self>) -> Result<(), String> {
+        self.listen_to(RdevSource)
+    }
+
+    /// Runs listener on events of `source`, blocking until it is exhausted.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if `source` could not be listened.
+    pub fn listen_to(self: &Arc<Self>, source: impl EventSource) -> Result<(), String> {
         let listener: Arc<_> = Arc::clone(self);
         *listener.listening.lock().unwrap() = true;
-        if let Err(err) = listen(move |event| listener.callback(event)) {
-            panic!("Error: {:?}", err);
-        }
+        let result = source.listen(move |event| listener.callback(event));
+        *self.listening.lock().unwrap() = false;
+        result
     }
     
     pub fn is_listening_key_event(&self) -> bool {
         *self.event_key.0.lock().unwrap()
     }
 
-    fn callback(&self, event: Event) {
-        let keybindings = self.minecraft.keybindings.lock().unwrap();
-
+    fn callback(self: &Arc<Self>, event: Event) {
         match event.event_type {
             EventType::KeyPress(key) => {
-                if self.is_listening_key_event(){
-                    let (running, lock, cvar) = &*Arc::clone(&self.event_key);
-                    *lock.lock().unwrap() = Some(key);
-                    *running.lock().unwrap() = false;
-                    cvar.notify_one();
-                }
-
-                if !self.is_running() { return }
-                if key == keybindings.start { self.service.start().unwrap_or(()) }
-                else {
-                    for [hotkey, slot] in keybindings.custom.iter() {
-                        if key == *hotkey {
-                            self.service.use_item(slot.clone());
-                            break;
-                        }
+                let held = self.press_modifier(key);
+                if self.is_listening_key_event() {
+                    if key == Key::Escape {
+                        self.finish_capture(None);
+                    } else if Modifiers::of(key).is_some() {
+                        // a lone modifier is captured on release, as it may start a chord
+                        *self.capture_modifier.lock().unwrap() = Some(key);
+                    } else {
+                        self.finish_capture(Some(Binding::new(key, held)));
                     }
                 }
+                self.press(key.into(), held);
             },
             EventType::KeyRelease(key) => {
-                if !self.is_running() { return }
-                if key == keybindings.start { self.service.pause().unwrap_or(()) }
+                let held = self.release_modifier(key);
+                if self.is_listening_key_event() && *self.capture_modifier.lock().unwrap() == Some(key) {
+                    self.finish_capture(Some(Binding::new(key, held)));
+                }
+                self.release(key.into());
             },
-            EventType::ButtonPress(_) => {
-                if self.is_listening_key_event(){
-                    let (running, lock, cvar) = &*Arc::clone(&self.event_key);
-                    *lock.lock().unwrap() = None;
-                    *running.lock().unwrap() = false;
-                    cvar.notify_one();
+            EventType::ButtonPress(button) => {
+                let held = Modifiers::from_keys(self.modifiers.lock().unwrap().iter());
+                if self.is_listening_key_event() {
+                    // left and right clicks are sent by the macro itself
+                    if matches!(button, Button::Left | Button::Right) {
+                        self.finish_capture(None);
+                    } else {
+                        self.finish_capture(Some(Binding::new(button, held)));
+                    }
                 }
+                self.press(button.into(), held);
             },
+            EventType::ButtonRelease(button) => self.release(button.into()),
             _ => ()
         }
     }
 
-    pub fn await_key(&self) -> Option<Key> {
+    fn press(self: &Arc<Self>, input: Input, held: Modifiers) {
+        if !self.is_running() { return }
+        let keybindings = self.minecraft.keybindings.lock().unwrap();
+        let matches = |binding: &Binding| binding.matches(input, held);
+        if matches(&keybindings.start) {
+            let activation = keybindings.activation;
+            drop(keybindings);
+            self.press_start(activation);
+        }
+        else if keybindings.profile_next.as_ref().is_some_and(matches) {
+            drop(keybindings);
+            self.switch_profile_later(input, |listener| listener.cycle_profile(1));
+        }
+        else if keybindings.profile_previous.as_ref().is_some_and(matches) {
+            drop(keybindings);
+            self.switch_profile_later(input, |listener| listener.cycle_profile(-1));
+        }
+        else if let Some((_, profile)) = keybindings.profile_select.iter()
+            .filter(|(hotkey, _)| matches(hotkey))
+            .max_by_key(|(hotkey, _)| hotkey.modifiers.count()) {
+            let profile = profile.clone();
+            drop(keybindings);
+            self.switch_profile_later(input, move |listener| listener.set_active_profile(&profile));
+        }
+        else if let Some(custom) = keybindings.custom.iter()
+            .filter(|custom| matches(&custom.hotkey))
+            .max_by_key(|custom| custom.hotkey.modifiers.count()) {
+            let (slot, action) = (custom.slot, custom.action.clone());
+            drop(keybindings);
+            self.service.use_item(slot, action);
+        }
+    }
+
+    fn release(&self, input: Input) {
+        let mut profile_held = self.profile_held.lock().unwrap();
+        if *profile_held == Some(input) { *profile_held = None }
+        drop(profile_held);
+
+        let keybindings = self.minecraft.keybindings.lock().unwrap();
+        if input != keybindings.start.input { return }
+        let activation = keybindings.activation;
+        drop(keybindings);
+        *self.start_held.lock().unwrap() = false;
+        if !self.is_running() { return }
+        if activation == minecraft::Activation::Hold { self.service.pause().unwrap_or(()) }
+    }
+
+    /// Records `key` if it is a modifier and returns the modifiers that were
+    /// already held before it.
+    fn press_modifier(&self, key: Key) -> Modifiers {
+        let mut pressed = self.modifiers.lock().unwrap();
+        let held = Modifiers::from_keys(pressed.iter().filter(|pressed| **pressed != key));
+        if Modifiers::of(key).is_some() && !pressed.contains(&key) {
+            pressed.push(key);
+        }
+        held
+    }
+
+    /// Forgets `key` and returns the modifiers still held.
+    fn release_modifier(&self, key: Key) -> Modifiers {
+        let mut pressed = self.modifiers.lock().unwrap();
+        pressed.retain(|pressed| *pressed != key);
+        Modifiers::from_keys(pressed.iter())
+    }
+
+    fn finish_capture(&self, binding: Option<Binding>) {
+        let (running, lock, cvar) = &*Arc::clone(&self.event_key);
+        *self.capture_modifier.lock().unwrap() = None;
+        *lock.lock().unwrap() = binding;
+        *running.lock().unwrap() = false;
+        cvar.notify_one();
+    }
+
+    fn press_start(&self, activation: minecraft::Activation) {
+        let mut held = self.start_held.lock().unwrap();
+        // key repeat while held
+        if *held { return }
+        *held = true;
+        drop(held);
+
+        match activation {
+            minecraft::Activation::Hold => self.service.start().unwrap_or(()),
+            minecraft::Activation::Toggle => {
+                if self.service.is_running() { self.service.pause().unwrap_or(()) }
+                else { self.service.start().unwrap_or(()) }
+            },
+            minecraft::Activation::Timed { millis } => {
+                self.service.start().unwrap_or(());
+                let mut id = self.activation_id.lock().unwrap();
+                *id += 1;
+                let current = *id;
+
+                let activation_id = Arc::clone(&self.activation_id);
+                let service = Arc::clone(&self.service);
+                let clock = Arc::clone(self.minecraft.clock());
+                let participant = Participant::new(&clock);
+                thread::spawn(move || {
+                    let _participant = participant;
+                    clock.sleep(Duration::from_millis(millis));
+                    if *activation_id.lock().unwrap() == current {
+                        service.pause().unwrap_or(());
+                    }
+                });
+            },
+        }
+    }
+
+    /// Runs `switch` of the profile hotkey pressed with `input` on another
+    /// thread, as saving and loading files in the input hook could get the
+    /// hook removed for being too slow. Repeats while it is held are ignored.
+    fn switch_profile_later(self: &Arc<Self>, input: Input, switch: impl FnOnce(&Self) -> Result<(), String> + Send + 'static) {
+        if self.profile_held.lock().unwrap().replace(input) == Some(input) { return }
+        let listener = Arc::clone(self);
+        thread::spawn(move || {
+            let _switching = listener.switching.lock().unwrap();
+            listener.switch_profile(switch);
+        });
+    }
+
+    /// Runs `switch`. The macro is paused if the new profile uses another
+    /// start key, as its release would go unnoticed.
+    fn switch_profile(&self, switch: impl FnOnce(&Self) -> Result<(), String>) {
+        let start = self.minecraft.keybindings.lock().unwrap().start;
+        if switch(self).is_err() { return }
+        if start != self.minecraft.keybindings.lock().unwrap().start {
+            *self.start_held.lock().unwrap() = false;
+            self.service.pause().unwrap_or(());
+        }
+    }
+
+    /// Blocks until a key, chord or mouse button is pressed and returns it.
+    ///
+    /// Returns `None` if capturing is cancelled with escape or a left or
+    /// right click, which cannot be bound.
+    pub fn await_key(&self) -> Option<Binding> {
         let (running, lock, cvar) = &*self.event_key;
         let mut running = running.lock().unwrap();
         if *running { return None }
         *running = true;
+        *self.capture_modifier.lock().unwrap() = None;
+        // locked before capturing shows, so the key cannot be set unseen
+        let key = lock.lock().unwrap();
         drop(running);
-        let key = cvar.wait(lock.lock().unwrap()).unwrap();
+        let key = cvar.wait(key).unwrap();
         *key
     }
 
@@ -128,37 +361,872 @@ impl Listener {
 
     pub fn is_running(&self) -> bool { *self.running.lock().unwrap() }
 
+    /// Counters of the macro since launch or the last `reset_stats`.
+    pub fn stats(&self) -> Snapshot { self.minecraft.stats.snapshot() }
+
+    pub fn reset_stats(&self) { self.minecraft.stats.reset() }
+
+    /// Saves settings and keybindings into the active profile once no more
+    /// changes follow for a moment.
     pub fn save_settings(&self) {
-        use crate::keyboard::SaveJson;
-        let mut files: Vec<_> = ["settings.json", "keybindings.json"]
-            .iter()
-            .map(|file| fs::OpenOptions::new()
-                .create(true)
-                .write(true)
-                .truncate(true)
-                .open(data_dir().join(file))
-                .unwrap()
-            )
-            .collect();
-        self.service.settings.lock().unwrap().to_json(&mut files[0]).unwrap();
-        self.minecraft.keybindings.lock().unwrap().to_json(&mut files[1]).unwrap();
+        self.saver.schedule(self.active_profile());
     }
-    
+
+    /// Writes changes waiting to be saved right away, e.g. before exiting.
+    pub fn flush_settings(&self) {
+        let (_writing, pending) = self.saver.pause();
+        if let Some(name) = pending { self.save_profile(&name) }
+    }
+
+    fn save_profile(&self, name: &str) {
+        keep_save_error(&self.save_errors, name, write_profile(&self.service, &self.minecraft, &self.unsaved, name));
+    }
+
+    /// Loads the profile used in the last session, creating the default
+    /// profile on first run.
     pub fn load_settings(&self) {
-        use crate::keyboard::SaveJson;
-        let files: Vec<_> = ["settings.json", "keybindings.json"]
-            .iter()
-            .map(|file| fs::File::open(data_dir().join(file)))
+        profile::migrate_legacy().unwrap_or(());
+        let name = profile::read_active()
+            .filter(|name| profile::exists(name))
+            .or_else(|| profile::list().into_iter().next())
+            .unwrap_or(String::from(profile::DEFAULT_PROFILE));
+        profile::profile_dir(&name).and_then(fs::create_dir_all).unwrap_or(());
+        self.load_profile(&name);
+    }
+
+    /// Loads profile `name` for this session only, the profile loaded next
+    /// session is left unchanged.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if the profile does not exist.
+    pub fn open_profile(&self, name: &str) -> Result<(), String> {
+        profile::migrate_legacy().unwrap_or(());
+        if !profile::exists(name) { return Err(format!("Profile {:?} does not exist", name)) }
+        self.load_profile(name);
+        Ok(())
+    }
+
+    /// Replaces settings and keybindings with those of profile `name`.
+    /// Missing files fall back to defaults, files of older versions are
+    /// upgraded and written back.
+    ///
+    /// Unreadable files also fall back to defaults. They are backed up, or
+    /// left out of saves while that fails, and their errors are kept for
+    /// `take_load_errors`.
+    fn load_profile(&self, name: &str) {
+        let files = read_profile(name);
+        self.apply_profile(name, files.settings, files.keybindings);
+
+        // never overwrite a file that could not be read before backing it up
+        let errors: Vec<_> = files.errors.into_iter()
+            .map(|(i, err)| {
+                let backup = profile::backup(name, &file_name(name, i)).ok();
+                if backup.is_none() { self.unsaved.lock().unwrap().push(err.file.clone()) }
+                LoadError { fallback: Fallback::Defaults { backup }, ..err }
+            })
             .collect();
+        if errors.is_empty() && files.migrated { self.save_profile(name) }
+        self.load_errors.lock().unwrap().extend(errors);
+    }
+
+    /// Both are swapped while holding their locks together, so the macro
+    /// thread never sees settings of one profile with keybindings of another.
+    /// They are locked in the order documented on [`Listener`].
+    fn apply_profile(&self, name: &str, settings: run::Settings, keybindings: minecraft::KeyBindings) {
+        let mut settings_lock = self.service.settings.lock().unwrap();
+        let mut keybindings_lock = self.minecraft.keybindings.lock().unwrap();
+        *settings_lock = settings;
+        *keybindings_lock = keybindings;
+        *self.profile.lock().unwrap() = String::from(name);
+        *self.generation.lock().unwrap() += 1;
+    }
+
+    /// Starts a thread reloading the active profile whenever another program
+    /// changes its files.
+    pub fn watch(self: &Arc<Self>) {
+        let listener = Arc::clone(self);
+        thread::spawn(move || {
+            let mut seen = None;
+            loop {
+                thread::sleep(WATCH_INTERVAL);
+                let name = listener.active_profile();
+                let modified = profile::FILES.map(|file| profile::path(&name, file)
+                    .and_then(fs::metadata)
+                    .and_then(|metadata| metadata.modified())
+                    .ok()
+                );
+                let current = Some((name, modified));
+                if current == seen { continue }
+                if listener.reload_profile() { seen = current }
+            }
+        });
+    }
+
+    /// Reloads the active profile if its files differ from the values in use.
+    /// Files that cannot be read are reported and the values in use are kept.
+    ///
+    /// Returns `false` if changes made in the app are waiting to be saved,
+    /// they are written over the files shortly.
+    fn reload_profile(&self) -> bool {
+        let _writing = self.saver.hold();
+        if self.saver.is_pending() { return false }
+
+        let name = self.active_profile();
+        let on_disk = profile::FILES.map(|file| profile::path(&name, file).and_then(fs::read_to_string));
+        // a file missing for a moment while an editor saves it is not a change
+        if on_disk.iter().any(Result::is_err) { return true }
+        if serialize_profile(&self.service, &self.minecraft, &name).is_ok_and(|in_use| in_use
+            .iter()
+            .zip(on_disk.iter())
+            .all(|(in_use, on_disk)| on_disk.as_ref().is_ok_and(|on_disk| on_disk == in_use))
+        ) {
+            return true
+        }
+
+        let files = read_profile(&name);
+        if !files.errors.is_empty() {
+            let errors = files.errors.into_iter().map(|(_, err)| LoadError { fallback: Fallback::Kept, ..err });
+            self.load_errors.lock().unwrap().extend(errors);
+            return true
+        }
+        self.switch_profile(|listener| {
+            listener.apply_profile(&name, files.settings, files.keybindings);
+            Ok(())
+        });
+        if files.migrated { self.save_profile(&name) }
+        true
+    }
+
+    /// Value of settings field `field`, a dot separated path like
+    /// `phases.0.count`. An empty path gives every setting.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if there is no such field.
+    pub fn get_setting(&self, field: &str) -> Result<Value, String> {
+        let settings = serde_json::to_value(&*self.service.settings.lock().unwrap()).map_err(|err| err.to_string())?;
+        settings.pointer(&pointer(field)).cloned().ok_or_else(|| format!("Unknown setting {:?}", field))
+    }
 
-        if let Ok(file) = &files[0] {
-            let mut reader = io::BufReader::new(file);
-            *self.service.settings.lock().unwrap() = run::Settings::from_json(&mut reader);
+    /// Replaces settings field `field`, a dot separated path like
+    /// `phases.0.count`, by `value` and saves the settings.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if there is no such field or `value` does not fit it.
+    pub fn set_setting(&self, field: &str, value: Value) -> Result<(), String> {
+        let mut settings = self.service.settings.lock().unwrap();
+        let mut tree = serde_json::to_value(&*settings).map_err(|err| err.to_string())?;
+        let slot = tree.pointer_mut(&pointer(field)).ok_or_else(|| format!("Unknown setting {:?}", field))?;
+        *slot = value;
+        let new: run::Settings = format::from_migrated(tree).map_err(|err| match err.field {
+            Some(field) => format!("{}: {}", field, err.message),
+            None => err.message,
+        })?;
+        new.validate()?;
+        if new.seed.is_some_and(|seed| seed > run::MAX_SEED) {
+            return Err(format!("seed: must be at most {}", run::MAX_SEED))
         }
+        *settings = new;
+        drop(settings);
 
-        if let Ok(file) = &files[1] {
-            let mut reader = io::BufReader::new(file);
-            *self.minecraft.keybindings.lock().unwrap() = minecraft::KeyBindings::from_json(&mut reader);
+        *self.generation.lock().unwrap() += 1;
+        self.save_settings();
+        Ok(())
+    }
+
+    /// Incremented whenever settings and keybindings are replaced by those
+    /// of a file or a control command, so views of them know to refresh.
+    pub fn generation(&self) -> u64 { *self.generation.lock().unwrap() }
+
+    /// Returns errors of files that could not be loaded since the last call.
+    pub fn take_load_errors(&self) -> Vec<LoadError> {
+        std::mem::take(&mut *self.load_errors.lock().unwrap())
+    }
+
+    /// Returns errors of profiles that could not be saved since the last
+    /// call, their changes are only kept in memory.
+    pub fn take_save_errors(&self) -> Vec<String> {
+        std::mem::take(&mut *self.save_errors.lock().unwrap())
+    }
+
+    pub fn active_profile(&self) -> String { self.profile.lock().unwrap().clone() }
+
+    pub fn profiles(&self) -> Vec<String> { profile::list() }
+
+    /// Switches to profile `name`, saving the current one first.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if the profile does not exist.
+    pub fn set_active_profile(&self, name: &str) -> Result<(), String> {
+        if !profile::exists(name) { return Err(format!("Profile {:?} does not exist", name)) }
+        let _writing = self.saver.pause();
+        self.save_profile(&self.active_profile());
+        self.load_profile(name);
+        profile::write_active(name).map_err(|err| err.to_string())
+    }
+
+    /// Switches to the profile `step` positions away from the active one in
+    /// alphabetical order, wrapping around at both ends.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if there is no profile to switch to.
+    pub fn cycle_profile(&self, step: isize) -> Result<(), String> {
+        let profiles = self.profiles();
+        if profiles.is_empty() { return Err(String::from("There is no profile")) }
+        let active = self.active_profile();
+        let index = profiles.iter().position(|profile| *profile == active).unwrap_or(0) as isize;
+        let index = (index + step).rem_euclid(profiles.len() as isize) as usize;
+        self.set_active_profile(&profiles[index])
+    }
+
+    /// Creates profile `name` with default settings and keybindings.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if name is invalid or already taken.
+    pub fn create_profile(&self, name: &str) -> Result<(), String> {
+        profile::create(name)?;
+        let settings = run::Settings::default().to_text(Format::Json, None)?;
+        profile::write(name, &file_name(name, 0), settings.as_bytes()).map_err(|err| err.to_string())?;
+        let keybindings = minecraft::KeyBindings::default().to_text(Format::Json, None)?;
+        profile::write(name, &file_name(name, 1), keybindings.as_bytes()).map_err(|err| err.to_string())
+    }
+
+    /// Rewrites files of profile `name` in `format`, removing those of the
+    /// other format.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if the profile does not exist or a file cannot be read,
+    /// nothing is converted then.
+    pub fn convert_profile(&self, name: &str, format: Format) -> Result<(), String> {
+        if !profile::exists(name) { return Err(format!("Profile {:?} does not exist", name)) }
+        let (_writing, pending) = self.saver.pause();
+        if let Some(pending) = pending { self.save_profile(&pending) }
+
+        let converted = [
+            convert_file::<run::Settings>(name, 0, format)?,
+            convert_file::<minecraft::KeyBindings>(name, 1, format)?,
+        ];
+        for (file, text) in profile::FILES.iter().zip(converted) {
+            let Some(text) = text else { continue };
+            let previous = profile::path(name, file).map_err(|err| err.to_string())?;
+            profile::write(name, &format.file_name(file), text.as_bytes()).map_err(|err| err.to_string())?;
+            if previous.is_file() { fs::remove_file(previous).map_err(|err| err.to_string())? }
         }
+        Ok(())
+    }
+
+    /// Bundles profile `name` to be shared.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if the profile does not exist or its files cannot be
+    /// read.
+    pub fn export_profile(&self, name: &str) -> Result<SharedProfile, String> {
+        if !profile::exists(name) { return Err(format!("Profile {:?} does not exist", name)) }
+        let (_writing, pending) = self.saver.pause();
+        if let Some(pending) = pending { self.save_profile(&pending) }
+
+        let files = read_profile(name);
+        if let Some((_, err)) = files.errors.into_iter().next() { return Err(err.to_string()) }
+        Ok(SharedProfile::new(name, files.settings, files.keybindings))
+    }
+
+    /// Creates profile `name` from a shared one. Hotkeys to profiles that do
+    /// not exist are kept, they work once such a profile is created.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if name is invalid or already taken.
+    pub fn import_profile(&self, shared: &SharedProfile, name: &str) -> Result<(), String> {
+        profile::create(name)?;
+        let settings = shared.settings.to_text(Format::Json, None)?;
+        profile::write(name, &file_name(name, 0), settings.as_bytes()).map_err(|err| err.to_string())?;
+        let keybindings = shared.keybindings.to_text(Format::Json, None)?;
+        profile::write(name, &file_name(name, 1), keybindings.as_bytes()).map_err(|err| err.to_string())
+    }
+
+    /// Copies profile `from` into a new profile `to`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if `from` does not exist or `to` is invalid or taken.
+    pub fn duplicate_profile(&self, from: &str, to: &str) -> Result<(), String> {
+        let (_writing, pending) = self.saver.pause();
+        let active = self.active_profile();
+        if pending.is_some() || from == active { self.save_profile(&active) }
+        profile::duplicate(from, to)
+    }
+
+    /// # Errors
+    ///
+    /// Returns `Err` if `from` does not exist or `to` is invalid or taken.
+    pub fn rename_profile(&self, from: &str, to: &str) -> Result<(), String> {
+        let (_writing, pending) = self.saver.pause();
+        if let Some(name) = pending { self.save_profile(&name) }
+        let mut active = self.profile.lock().unwrap();
+        profile::rename(from, to)?;
+        if *active == from {
+            *active = String::from(to);
+            profile::write_active(to).map_err(|err| err.to_string())?;
+        }
+        Ok(())
+    }
+
+    /// Deletes profile `name`, switching to another one if it is active.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if the profile does not exist or is the only one left.
+    pub fn delete_profile(&self, name: &str) -> Result<(), String> {
+        if !profile::exists(name) { return Err(format!("Profile {:?} does not exist", name)) }
+        let fallback = profile::list().into_iter().find(|profile| profile != name)
+            .ok_or(String::from("Cannot delete the only profile"))?;
+        if name == self.active_profile() {
+            self.set_active_profile(&fallback)?;
+        }
+        profile::delete(name)
+    }
+}
+
+
+
+/// JSON pointer of dot separated `field`.
+fn pointer(field: &str) -> String {
+    if field.is_empty() { return String::new() }
+    field.split('.').map(|key| format!("/{}", key.replace('~', "~0").replace('/', "~1"))).collect()
+}
+
+/// Name of the `i`th of `profile::FILES` of profile `name` on disk.
+fn file_name(name: &str, i: usize) -> String {
+    profile::format_of(name, profile::FILES[i]).file_name(profile::FILES[i])
+}
+
+/// Current settings and keybindings as the text of `profile::FILES` of
+/// profile `name`, in the format and layout they are stored in.
+fn serialize_profile(service: &run::MacroService, minecraft: &minecraft::Minecraft, name: &str) -> io::Result<[String; 2]> {
+    let previous = profile::FILES.map(|file| profile::path(name, file).and_then(fs::read_to_string).ok());
+    let settings = service.settings.lock().unwrap()
+        .to_text(profile::format_of(name, profile::FILES[0]), previous[0].as_deref())
+        .map_err(io::Error::other)?;
+    let keybindings = minecraft.keybindings.lock().unwrap()
+        .to_text(profile::format_of(name, profile::FILES[1]), previous[1].as_deref())
+        .map_err(io::Error::other)?;
+    Ok([settings, keybindings])
+}
+
+/// Writes current settings and keybindings into profile `name`. Files in
+/// `unsaved` are skipped unless they can be backed up now.
+fn write_profile(
+    service: &run::MacroService,
+    minecraft: &minecraft::Minecraft,
+    unsaved: &Mutex<Vec<PathBuf>>,
+    name: &str,
+) -> io::Result<()> {
+    let texts = serialize_profile(service, minecraft, name)?;
+    for (i, text) in texts.iter().enumerate() {
+        let file = file_name(name, i);
+        let path = profile::profile_dir(name)?.join(&file);
+        let mut unsaved = unsaved.lock().unwrap();
+        if unsaved.contains(&path) {
+            if profile::backup(name, &file).is_err() { continue }
+            unsaved.retain(|unsaved| *unsaved != path);
+        }
+        drop(unsaved);
+        profile::write(name, &file, text.as_bytes())?;
+    }
+    Ok(())
+}
+
+/// Keeps the error of a failed save of profile `name` for
+/// `Listener::take_save_errors`.
+fn keep_save_error(errors: &Mutex<Vec<String>>, name: &str, result: io::Result<()>) {
+    if let Err(err) = result {
+        errors.lock().unwrap().push(format!("Profile {:?} could not be saved: {}", name, err));
+    }
+}
+
+/// Text of the `i`th of `profile::FILES` of profile `name` in `format`, or
+/// `None` if it is stored in `format` already. Missing files are converted
+/// as defaults.
+fn convert_file<T: SaveJson>(name: &str, i: usize, format: Format) -> Result<Option<String>, String> {
+    let from = profile::format_of(name, profile::FILES[i]);
+    if from == format { return Ok(None) }
+    let path = profile::path(name, profile::FILES[i]).map_err(|err| err.to_string())?;
+    let value = match fs::read_to_string(&path) {
+        Ok(text) => T::from_text(&text, from).map_err(|err| LoadError { file: path, ..err }.to_string())?,
+        Err(_) => T::default(),
+    };
+    value.to_text(format, None).map(Some)
+}
+
+
+/// Profile as read from disk.
+struct ProfileFiles {
+    settings: run::Settings,
+    keybindings: minecraft::KeyBindings,
+    /// Errors of files replaced by defaults, with their index in
+    /// `profile::FILES`.
+    errors: Vec<(usize, LoadError)>,
+    /// Whether any file was upgraded from an older version.
+    migrated: bool,
+}
+
+fn read_profile(name: &str) -> ProfileFiles {
+    use crate::keyboard::schema;
+    let paths = profile::FILES.map(|file| profile::path(name, file));
+    let texts = paths.each_ref().map(|path| match path {
+        Ok(path) => fs::read_to_string(path),
+        // no data directory, reported like an unreadable file
+        Err(err) => Err(io::Error::other(err.to_string())),
+    });
+    let paths = [0, 1].map(|i| paths[i].as_ref().map_or_else(|_| PathBuf::from(profile::FILES[i]), PathBuf::clone));
+    let formats = paths.each_ref().map(|path| Format::of(path).unwrap_or(Format::Json));
+    let mut values = [0, 1].map(|i| match &texts[i] {
+        Ok(text) => formats[i].parse(text).map(Some),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(LoadError::new(err.to_string())),
+    });
+
+    let migrated = match &mut values {
+        [settings, keybindings] => schema::migrate_profile(
+            settings.as_mut().ok().and_then(Option::as_mut),
+            keybindings.as_mut().ok().and_then(Option::as_mut),
+        ),
+    };
+    let [settings, keybindings] = values;
+    let settings = load_value::<run::Settings>(&texts[0], formats[0], settings, migrated[0]);
+    let keybindings = load_value::<minecraft::KeyBindings>(&texts[1], formats[1], keybindings, migrated[1]);
+
+    let mut errors = Vec::new();
+    let mut failed = |i: usize, err: LoadError| errors.push((i, LoadError { file: paths[i].clone(), ..err }));
+    let settings = settings.unwrap_or_else(|err| { failed(0, err); Default::default() });
+    let keybindings = keybindings.unwrap_or_else(|err| { failed(1, err); Default::default() });
+
+    ProfileFiles { settings, keybindings, errors, migrated: migrated.contains(&true) }
+}
+
+/// Deserializes a file read as `text`, defaults if the file is missing.
+fn load_value<T: SaveJson>(
+    text: &io::Result<String>,
+    format: Format,
+    value: Result<Option<serde_json::Value>, LoadError>,
+    migrated: bool,
+) -> Result<T, LoadError> {
+    match value? {
+        Some(value) => T::from_value(text.as_deref().unwrap_or_default(), format, value, migrated),
+        None => Ok(T::default()),
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyboard::{
+        clock::VirtualClock,
+        sink::RecordingSink,
+        source::{synthetic, ChannelSource},
+    };
+
+    /// Armed listener on a virtual clock, with the sink recording its events.
+    fn armed(activation: minecraft::Activation) -> (Arc<Listener>, Arc<VirtualClock>, RecordingSink) {
+        let clock = VirtualClock::new();
+        let sink = RecordingSink::with_clock(clock.clone());
+        let listener = Listener::with_clock(sink.clone(), clock.clone());
+        listener.minecraft.keybindings.lock().unwrap().activation = activation;
+        listener.start().unwrap();
+        clock.settle();
+        (listener, clock, sink)
+    }
+
+    /// Handles `events` on this thread, so all of them are seen before the
+    /// clock moves on.
+    fn feed(listener: &Arc<Listener>, clock: &VirtualClock, events: impl IntoIterator<Item = EventType>) {
+        let (tx, source) = ChannelSource::new();
+        for event in events { tx.send(synthetic(event)).unwrap() }
+        drop(tx);
+        listener.listen_to(source).unwrap();
+        clock.settle();
+    }
+
+    /// Creates profile `name` from the files of fixture version `version`,
+    /// in a data directory removed once the returned guard is dropped.
+    fn fixture_profile(name: &str, version: u64) -> crate::TestDataDir {
+        let dir = crate::TestDataDir::new(name);
+        let fixtures = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join(format!("tests/fixtures/v{}", version));
+        fs::create_dir_all(profile::profile_dir(name).unwrap()).unwrap();
+        for file in profile::FILES.map(|file| Format::Json.file_name(file)) {
+            fs::copy(fixtures.join(&file), profile::profile_dir(name).unwrap().join(&file)).unwrap();
+        }
+        dir
+    }
+
+    fn uniform(phase: &run::Phase) -> (f64, u64, u64) {
+        match phase.distribution {
+            run::Distribution::Uniform { ratio } => (ratio, phase.min_micros, phase.max_micros),
+            ref distribution => panic!("{:?} is not uniform", distribution),
+        }
+    }
+
+    #[test]
+    fn fixture_v0() {
+        let _dir = fixture_profile("fixture-v0", 0);
+        let files = read_profile("fixture-v0");
+        assert!(files.errors.is_empty() && files.migrated);
+
+        // the pairs became a phase each, with the slots of the keybindings
+        let phases = &files.settings.phases;
+        assert_eq!(phases.len(), 2);
+        assert_eq!((phases[0].slot, phases[0].button, phases[0].count, phases[0].sleep_micros), (Key::Num2, Button::Left, 6, 70_000));
+        assert_eq!((phases[1].slot, phases[1].button, phases[1].count, phases[1].sleep_micros), (Key::Num8, Button::Right, 4, 45_000));
+        assert_eq!(uniform(&phases[0]), (0.25, run::MIN_MICROS, run::MAX_MICROS));
+        assert_eq!(uniform(&phases[1]), (0.25, run::MIN_MICROS, run::MAX_MICROS));
+
+        let keybindings = &files.keybindings;
+        assert_eq!(keybindings.start, Binding::from(Key::ControlLeft));
+        let custom: Vec<_> = keybindings.custom.iter().map(|custom| (custom.hotkey, custom.slot, custom.action.clone())).collect();
+        assert_eq!(custom, [
+            (Binding::from(Key::KeyX), Key::Num3, minecraft::Action::Use),
+            (Binding::from(Key::KeyG), Key::Num9, minecraft::Action::Use),
+        ]);
+
+        // written back upgraded once loaded
+        Listener::with_sink(RecordingSink::new()).open_profile("fixture-v0").unwrap();
+        let files = read_profile("fixture-v0");
+        assert!(files.errors.is_empty() && !files.migrated);
+        assert_eq!(files.settings.phases[1].slot, Key::Num8);
+    }
+
+    #[test]
+    fn fixture_v1() {
+        let _dir = fixture_profile("fixture-v1", 1);
+        let files = read_profile("fixture-v1");
+        assert!(files.errors.is_empty() && files.migrated);
+
+        let settings = &files.settings;
+        assert_eq!(uniform(&settings.phases[0]), (0.1, run::MIN_MICROS, run::MAX_MICROS));
+        assert_eq!(uniform(&settings.phases[1]), (0.0, run::MIN_MICROS, run::MAX_MICROS));
+        assert_eq!(settings.return_slot, run::ReturnSlot::Slot(Key::Num1));
+        assert_eq!(settings.return_delay_millis, 150);
+        assert_eq!(settings.seed, None);
+
+        let keybindings = &files.keybindings;
+        assert_eq!(keybindings.start, Binding::new(Key::KeyR, Modifiers { ctrl: true, ..Modifiers::NONE }));
+        assert_eq!(keybindings.activation, minecraft::Activation::Timed { millis: 2000 });
+        assert_eq!(keybindings.custom[1].hotkey, Binding::from(Button::Unknown(8)));
+        assert_eq!(keybindings.custom[1].action, minecraft::Action::Hold { millis: 1600 });
+        assert_eq!(keybindings.custom[2].action, minecraft::Action::Sequence(vec![
+            minecraft::Step { hold_millis: 20, ..minecraft::Step::click(Button::Left) },
+        ]));
+        assert_eq!(keybindings.profile_next, Some(Binding::from(Key::F2)));
+        assert_eq!(keybindings.profile_select, [(Binding::from(Key::F5), String::from("pvp"))]);
+    }
+
+    #[test]
+    fn fixture_v2() {
+        let _dir = fixture_profile("fixture-v2", 2);
+        let files = read_profile("fixture-v2");
+        assert!(files.errors.is_empty() && !files.migrated);
+
+        let settings = &files.settings;
+        assert_eq!(settings.phases[0].distribution, run::Distribution::Normal { ratio: 0.15 });
+        assert_eq!(settings.phases[1].distribution, run::Distribution::Table { micros: vec![45_000, 50_000, 58_000] });
+        assert_eq!((settings.phases[1].min_micros, settings.phases[1].max_micros), (20_000, 100_000));
+        assert_eq!(settings.return_slot, run::ReturnSlot::Stay);
+        assert_eq!(settings.seed, Some(42));
+        assert_eq!(files.keybindings.custom[0].action, minecraft::Action::DoubleUse);
+    }
+
+    #[test]
+    fn convert() {
+        let _dir = fixture_profile("convert", 2);
+        let listener = Listener::with_sink(RecordingSink::new());
+        let values = |files: &ProfileFiles| (
+            serde_json::to_value(&files.settings).unwrap(),
+            serde_json::to_value(&files.keybindings).unwrap(),
+        );
+        let original = values(&read_profile("convert"));
+
+        for format in [Format::Toml, Format::Json, Format::Json] {
+            listener.convert_profile("convert", format).unwrap();
+            let names: Vec<_> = fs::read_dir(profile::profile_dir("convert").unwrap()).unwrap()
+                .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+                .collect();
+            assert_eq!(names.len(), 2, "{:?}", names);
+            for file in profile::FILES {
+                assert_eq!(profile::format_of("convert", file), format);
+                assert!(names.contains(&format.file_name(file)));
+            }
+            let files = read_profile("convert");
+            assert!(files.errors.is_empty() && !files.migrated);
+            assert_eq!(values(&files), original);
+        }
+
+        assert_eq!(listener.convert_profile("missing", Format::Toml), Err(String::from("Profile \"missing\" does not exist")));
+    }
+
+    fn clicks(sink: &RecordingSink) -> usize {
+        sink.events().iter().filter(|(_, event)| matches!(event, EventType::ButtonPress(_))).count()
+    }
+
+    #[test]
+    fn hold() {
+        let (listener, clock, sink) = armed(minecraft::Activation::Hold);
+        feed(&listener, &clock, [EventType::KeyPress(Key::ControlLeft)]);
+        assert!(listener.service.is_running());
+        clock.advance(Duration::from_millis(500));
+        let clicked = clicks(&sink);
+        assert!(clicked > 0);
+
+        // key repeat keeps it running
+        feed(&listener, &clock, [EventType::KeyPress(Key::ControlLeft)]);
+        assert!(listener.service.is_running());
+
+        feed(&listener, &clock, [EventType::KeyRelease(Key::ControlLeft)]);
+        assert!(!listener.service.is_running());
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(clicks(&sink), clicked);
+
+        // resumes on the next press
+        feed(&listener, &clock, [EventType::KeyPress(Key::ControlLeft)]);
+        clock.advance(Duration::from_millis(500));
+        assert!(clicks(&sink) > clicked);
+    }
+
+    #[test]
+    fn toggle() {
+        let (listener, clock, sink) = armed(minecraft::Activation::Toggle);
+        let tap = [EventType::KeyPress(Key::ControlLeft), EventType::KeyRelease(Key::ControlLeft)];
+        feed(&listener, &clock, tap);
+        assert!(listener.service.is_running());
+        clock.advance(Duration::from_millis(500));
+
+        feed(&listener, &clock, tap);
+        assert!(!listener.service.is_running());
+        let clicked = clicks(&sink);
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(clicks(&sink), clicked);
+
+        feed(&listener, &clock, tap);
+        assert!(listener.service.is_running());
+    }
+
+    #[test]
+    fn timed() {
+        let (listener, clock, _sink) = armed(minecraft::Activation::Timed { millis: 300 });
+        let tap = [EventType::KeyPress(Key::ControlLeft), EventType::KeyRelease(Key::ControlLeft)];
+        feed(&listener, &clock, tap);
+        clock.advance(Duration::from_millis(200));
+        assert!(listener.service.is_running());
+
+        // pressing again restarts the countdown
+        feed(&listener, &clock, tap);
+        clock.advance(Duration::from_millis(200));
+        assert!(listener.service.is_running());
+        clock.advance(Duration::from_millis(100));
+        assert!(!listener.service.is_running());
+    }
+
+    #[test]
+    fn disarmed() {
+        let (listener, clock, sink) = armed(minecraft::Activation::Hold);
+        listener.stop().unwrap();
+        feed(&listener, &clock, [EventType::KeyPress(Key::ControlLeft)]);
+        clock.advance(Duration::from_millis(500));
+        assert!(!listener.service.is_running());
+        assert_eq!(clicks(&sink), 0);
+    }
+
+    /// Waits for the profile switches started by hotkeys.
+    /// Binding captured by `await_key` while `events` are handled.
+    fn capture(listener: &Arc<Listener>, events: impl IntoIterator<Item = EventType>) -> Option<Binding> {
+        let capturing = Arc::clone(listener);
+        let captured = thread::spawn(move || capturing.await_key());
+        while !listener.is_listening_key_event() { thread::yield_now() }
+        let (tx, source) = ChannelSource::new();
+        for event in events { tx.send(synthetic(event)).unwrap() }
+        drop(tx);
+        listener.listen_to(source).unwrap();
+        captured.join().unwrap()
+    }
+
+    #[test]
+    fn captures() {
+        use EventType::{KeyPress, KeyRelease};
+        let listener = Listener::with_sink(RecordingSink::new());
+        let shift = Modifiers { shift: true, ..Modifiers::NONE };
+        let ctrl = Modifiers { ctrl: true, ..Modifiers::NONE };
+
+        let chord = [KeyPress(Key::ShiftLeft), KeyPress(Key::KeyX), KeyRelease(Key::KeyX), KeyRelease(Key::ShiftLeft)];
+        assert_eq!(capture(&listener, chord), Some(Binding::new(Key::KeyX, shift)));
+        // a lone modifier, once released
+        let lone = [KeyPress(Key::ShiftLeft), KeyRelease(Key::ShiftLeft)];
+        assert_eq!(capture(&listener, lone), Some(Binding::from(Key::ShiftLeft)));
+        let held = [KeyPress(Key::ControlLeft), KeyPress(Key::ShiftLeft), KeyRelease(Key::ShiftLeft), KeyRelease(Key::ControlLeft)];
+        assert_eq!(capture(&listener, held), Some(Binding::new(Key::ShiftLeft, ctrl)));
+        assert_eq!(capture(&listener, [KeyPress(Key::Escape), KeyRelease(Key::Escape)]), None);
+        assert!(!listener.is_listening_key_event());
+    }
+
+    #[test]
+    fn custom_hotkeys() {
+        use EventType::{KeyPress, KeyRelease, ButtonPress};
+        let (listener, clock, sink) = armed(minecraft::Activation::Hold);
+        let shift = Modifiers { shift: true, ..Modifiers::NONE };
+        listener.minecraft.keybindings.lock().unwrap().custom = vec![
+            minecraft::Custom::new(Key::KeyX, Key::Num3, minecraft::Action::Use),
+            minecraft::Custom::new(Binding::new(Key::KeyX, shift), Key::Num7, minecraft::Action::Punch),
+        ];
+        // no switching back to the slot used before
+        listener.service.settings.lock().unwrap().return_slot = run::ReturnSlot::Stay;
+        let used = |events: Vec<EventType>| {
+            sink.clear();
+            feed(&listener, &clock, events);
+            clock.advance(Duration::from_secs(1));
+            sink.event_types().into_iter()
+                .filter(|event| matches!(event, KeyPress(Key::Num3 | Key::Num7) | ButtonPress(_)))
+                .collect::<Vec<_>>()
+        };
+
+        // the chord wins over the bare key it contains
+        let chord = used(vec![KeyPress(Key::ShiftLeft), KeyPress(Key::KeyX), KeyRelease(Key::KeyX), KeyRelease(Key::ShiftLeft)]);
+        assert_eq!(chord, [KeyPress(Key::Num7), ButtonPress(Button::Left)]);
+        assert_eq!(used(vec![KeyPress(Key::KeyX), KeyRelease(Key::KeyX)]), [KeyPress(Key::Num3), ButtonPress(Button::Right)]);
+
+        // a bare key still works while another modifier is held
+        let ctrl = used(vec![KeyPress(Key::ControlRight), KeyPress(Key::KeyX), KeyRelease(Key::KeyX), KeyRelease(Key::ControlRight)]);
+        assert_eq!(ctrl, [KeyPress(Key::Num3), ButtonPress(Button::Right)]);
+    }
+
+    #[test]
+    fn side_buttons() {
+        use EventType::{KeyPress, KeyRelease, ButtonPress, ButtonRelease};
+        let shift = Modifiers { shift: true, ..Modifiers::NONE };
+
+        // side buttons can be bound, the clicks the macro sends cannot
+        let listener = Listener::with_sink(RecordingSink::new());
+        let side = [KeyPress(Key::ShiftLeft), ButtonPress(Button::Unknown(8)), ButtonRelease(Button::Unknown(8)), KeyRelease(Key::ShiftLeft)];
+        assert_eq!(capture(&listener, side), Some(Binding::new(Button::Unknown(8), shift)));
+        assert_eq!(capture(&listener, [ButtonPress(Button::Middle), ButtonRelease(Button::Middle)]), Some(Binding::from(Button::Middle)));
+        assert_eq!(capture(&listener, [ButtonPress(Button::Left), ButtonRelease(Button::Left)]), None);
+        assert_eq!(capture(&listener, [ButtonPress(Button::Right), ButtonRelease(Button::Right)]), None);
+
+        let (listener, clock, sink) = armed(minecraft::Activation::Hold);
+        listener.minecraft.keybindings.lock().unwrap().custom = vec![
+            minecraft::Custom::new(Button::Unknown(8), Key::Num9, minecraft::Action::Use),
+            minecraft::Custom::new(Binding::new(Button::Unknown(8), shift), Key::Num8, minecraft::Action::Punch),
+        ];
+        listener.service.settings.lock().unwrap().return_slot = run::ReturnSlot::Stay;
+        let used = |events: Vec<EventType>| {
+            sink.clear();
+            feed(&listener, &clock, events);
+            clock.advance(Duration::from_secs(1));
+            sink.event_types().into_iter()
+                .filter(|event| matches!(event, KeyPress(Key::Num8 | Key::Num9) | ButtonPress(_)))
+                .collect::<Vec<_>>()
+        };
+
+        let side = vec![ButtonPress(Button::Unknown(8)), ButtonRelease(Button::Unknown(8))];
+        assert_eq!(used(side), [KeyPress(Key::Num9), ButtonPress(Button::Right)]);
+        let side = vec![KeyPress(Key::ShiftLeft), ButtonPress(Button::Unknown(8)), ButtonRelease(Button::Unknown(8)), KeyRelease(Key::ShiftLeft)];
+        assert_eq!(used(side), [KeyPress(Key::Num8), ButtonPress(Button::Left)]);
+        assert!(used(vec![ButtonPress(Button::Unknown(9)), ButtonRelease(Button::Unknown(9))]).is_empty());
+    }
+
+    fn switched_to(listener: &Listener, name: &str) -> bool {
+        for _ in 0..100 {
+            if listener.active_profile() == name { break }
+            thread::sleep(Duration::from_millis(10));
+        }
+        thread::sleep(Duration::from_millis(100));
+        drop(listener.switching.lock().unwrap());
+        listener.active_profile() == name
+    }
+
+    #[test]
+    fn profile_hotkey_repeat() {
+        let _dir = crate::TestDataDir::new("profile-hotkey");
+        for name in ["a", "b", "c"] { profile::create(name).unwrap() }
+        let (listener, clock, _sink) = armed(minecraft::Activation::Hold);
+        listener.open_profile("a").unwrap();
+        listener.minecraft.keybindings.lock().unwrap().profile_next = Some(Binding::from(Key::F2));
+
+        // key repeats of the held hotkey switch only once
+        feed(&listener, &clock, [EventType::KeyPress(Key::F2); 3]);
+        assert!(switched_to(&listener, "b"));
+
+        listener.minecraft.keybindings.lock().unwrap().profile_next = Some(Binding::from(Key::F2));
+        feed(&listener, &clock, [EventType::KeyRelease(Key::F2), EventType::KeyPress(Key::F2)]);
+        assert!(switched_to(&listener, "c"));
+    }
+
+    #[test]
+    fn save_error() {
+        let _dir = crate::TestDataDir::new("save-error");
+        profile::create("x").unwrap();
+        let listener = Listener::with_sink(RecordingSink::new());
+        listener.open_profile("x").unwrap();
+        // a folder in place of the file cannot be replaced
+        fs::create_dir_all(profile::profile_dir("x").unwrap().join("settings.json/kept")).unwrap();
+
+        listener.save_settings();
+        listener.flush_settings();
+        let errors = listener.take_save_errors();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("Profile \"x\" could not be saved: "));
+        assert!(listener.take_save_errors().is_empty());
+    }
+
+    #[test]
+    fn setting_paths() {
+        assert_eq!(pointer(""), "");
+        assert_eq!(pointer("seed"), "/seed");
+        assert_eq!(pointer("phases.0.count"), "/phases/0/count");
+        assert_eq!(pointer("a/b.c~"), "/a~1b/c~0");
+    }
+
+    #[test]
+    fn settings() {
+        let _dir = crate::TestDataDir::new("settings");
+        let listener = Listener::with_sink(RecordingSink::new());
+        assert_eq!(listener.get_setting("phases.0.count"), Ok(Value::from(7)));
+        assert_eq!(listener.get_setting(""), serde_json::to_value(run::Settings::default()).map_err(|err| err.to_string()));
+
+        listener.set_setting("phases.0.count", Value::from(3)).unwrap();
+        listener.set_setting("phases.1.distribution", serde_json::json!({"Normal": {"ratio": 0.1}})).unwrap();
+        listener.set_setting("seed", Value::from(9)).unwrap();
+        assert_eq!(listener.get_setting("phases.0.count"), Ok(Value::from(3)));
+        assert_eq!(listener.get_setting("phases.1.distribution.Normal.ratio"), Ok(Value::from(0.1)));
+        let settings = listener.service.settings.lock().unwrap().clone();
+        assert_eq!((settings.phases[0].count, settings.seed), (3, Some(9)));
+        assert_eq!(settings.phases[1].distribution, run::Distribution::Normal { ratio: 0.1 });
+
+        for field in ["phases.2.count", "phases.0.speed", "phases.x", "seed.0"] {
+            assert_eq!(listener.get_setting(field), Err(format!("Unknown setting {:?}", field)));
+            assert_eq!(listener.set_setting(field, Value::from(1)), Err(format!("Unknown setting {:?}", field)));
+        }
+        let err = listener.set_setting("phases.0.count", Value::from("many")).unwrap_err();
+        assert!(err.starts_with("phases[0].count: invalid type"), "{}", err);
+        let err = listener.set_setting("return_slot", Value::from("Left")).unwrap_err();
+        assert!(err.starts_with("return_slot: unknown variant"), "{}", err);
+        assert!(listener.set_setting("phases.0.count", Value::from(-1)).is_err());
+
+        // refused values leave the settings as they were
+        assert_eq!(serde_json::to_value(&*listener.service.settings.lock().unwrap()).unwrap(), serde_json::to_value(&settings).unwrap());
+    }
+
+    #[test]
+    fn seed_too_large() {
+        let listener = Listener::with_sink(RecordingSink::new());
+        let seed = Value::from(run::MAX_SEED + 1);
+        assert_eq!(listener.set_setting("seed", seed), Err(format!("seed: must be at most {}", run::MAX_SEED)));
+        assert_eq!(listener.service.settings.lock().unwrap().seed, None);
     }
 }
@@ -1,45 +1,160 @@
 use rdev::{
     Key, Button,
     EventType,
-    simulate
 };
 
 use serde::{Serialize, Deserialize};
 
 use std::{
-    cell::RefCell, rc::Rc,
-    sync::{ mpsc, Arc, Mutex },
+    cell::RefCell, collections::VecDeque, iter, rc::Rc,
+    sync::{ mpsc::{self, RecvTimeoutError, TryRecvError}, Arc, Mutex },
     thread, time::Duration,
 };
 
+use super::{
+    binding::Binding,
+    clock::{Clock, Participant, RealClock},
+    run::ReturnSlot,
+    sink::{InputSink, RdevSink},
+    stats::Stats,
+};
+
+
+
+/// How long a click holds its button, the least time the worker takes for
+/// every click.
+pub const PRESS: Duration = Duration::from_millis(30);
+
+/// Phase clicks sent later than this after their deadline are dropped, as
+/// the worker fell behind the macro.
+const STALE: Duration = Duration::from_millis(100);
 
 
 enum Message {
-    UseItem(Key, bool),
-    PunchItem(Key, bool),
+    /// Click of the macro phase at the index, due at the deadline, if any.
+    Click(Key, Button, Option<(usize, Duration)>),
+    Custom(Key, Action, ReturnSlot, Duration),
+    Select(Key),
+}
+
+
+/// Single click of a custom action.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Step {
+    /// Slot to select, the slot of the custom binding if `None`.
+    #[serde(default)]
+    pub slot: Option<Key>,
+    pub button: Button,
+    /// Time the button is held down, never less than `PRESS`.
+    #[serde(default)]
+    pub hold_millis: u64,
+    /// Wait after releasing the button.
+    #[serde(default)]
+    pub delay_millis: u64,
+}
+
+impl Step {
+    pub fn click(button: Button) -> Self {
+        Self { slot: None, button, hold_millis: 0, delay_millis: 0 }
+    }
+}
+
+
+/// What a custom binding does with its slot.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub enum Action {
+    /// Right clicks once.
+    #[default]
+    Use,
+    /// Left clicks once.
+    Punch,
+    /// Holds right click for `millis`, e.g. to eat or charge a bow.
+    Hold { millis: u64 },
+    /// Right clicks twice.
+    DoubleUse,
+    /// Runs steps in order.
+    Sequence(Vec<Step>),
+}
+
+impl Action {
+    /// Expands the action into steps run on `slot`.
+    pub fn steps(&self, slot: Key) -> Vec<Step> {
+        let mut steps = match self {
+            Action::Use => vec![Step::click(Button::Right)],
+            Action::Punch => vec![Step::click(Button::Left)],
+            Action::Hold { millis } => vec![Step { hold_millis: *millis, ..Step::click(Button::Right) }],
+            Action::DoubleUse => vec![Step::click(Button::Right), Step::click(Button::Right)],
+            Action::Sequence(steps) => steps.clone(),
+        };
+        for step in steps.iter_mut() {
+            step.slot.get_or_insert(slot);
+        }
+        steps
+    }
+}
+
+
+/// Hotkey running an action on a hotbar slot.
+///
+/// Also deserializes from the `[hotkey, slot]` pairs of older keybindings.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Custom {
+    pub hotkey: Binding,
+    pub slot: Key,
+    #[serde(default)]
+    pub action: Action,
+}
+
+impl Custom {
+    pub fn new(hotkey: impl Into<Binding>, slot: Key, action: Action) -> Self {
+        Self { hotkey: hotkey.into(), slot, action }
+    }
+}
+
+
+/// How the start key controls the macro.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum Activation {
+    /// Runs while the key is held.
+    #[default]
+    Hold,
+    /// Each press starts or stops the macro.
+    Toggle,
+    /// Each press runs the macro for `millis`, pressing again while running
+    /// restarts the countdown.
+    Timed { millis: u64 },
 }
 
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct KeyBindings {
-    pub start: Key,
-    pub sword: Key,
-    pub fishing_rod: Key,
-    pub custom: Vec<[Key; 2]>,
+    pub start: Binding,
+    #[serde(default)]
+    pub activation: Activation,
+    pub custom: Vec<Custom>,
+    #[serde(default)]
+    pub profile_next: Option<Binding>,
+    #[serde(default)]
+    pub profile_previous: Option<Binding>,
+    /// Hotkeys switching directly to the named profile.
+    #[serde(default)]
+    pub profile_select: Vec<(Binding, String)>,
 }
 
 impl Default for KeyBindings {
     fn default() -> Self {
         Self {
-            start: Key::ControlLeft,
-            sword: Key::Num1,
-            fishing_rod: Key::Num2,
+            start: Key::ControlLeft.into(),
+            activation: Activation::Hold,
             custom: vec![
-                [Key::KeyX, Key::Num3],
-                [Key::KeyC, Key::Num4],
-                [Key::KeyV, Key::Num5],
-                [Key::KeyF, Key::Num6],
-            ]
+                Custom::new(Key::KeyX, Key::Num3, Action::Use),
+                Custom::new(Key::KeyC, Key::Num4, Action::Use),
+                Custom::new(Key::KeyV, Key::Num5, Action::Use),
+                Custom::new(Key::KeyF, Key::Num6, Action::Use),
+            ],
+            profile_next: None,
+            profile_previous: None,
+            profile_select: Vec::new(),
         }
     }
 }
@@ -47,95 +162,230 @@ impl Default for KeyBindings {
 
 pub struct Minecraft {
     tx: Arc<mpsc::Sender<Message>>,
-    busy: Arc<Mutex<bool>>,
+    /// Hotbar slot selected last by the worker.
+    selected: Arc<Mutex<Option<Key>>>,
+    /// Phase clicks due until then are dropped, see `drop_phase_clicks`.
+    stopped: Arc<Mutex<Option<Duration>>>,
     pub keybindings: Mutex<KeyBindings>,
+    pub stats: Arc<Stats>,
+    clock: Arc<dyn Clock>,
 }
 
 impl Minecraft {
     pub fn new() -> Arc<Self> {
+        Self::with_sink(RdevSink)
+    }
+
+    /// Creates a worker that emits its events into `sink`.
+    pub fn with_sink<S: InputSink>(sink: S) -> Arc<Self> {
+        Self::with_clock(sink, RealClock::new())
+    }
+
+    /// Creates a worker that emits its events into `sink`, timing them on
+    /// `clock`.
+    pub fn with_clock<S: InputSink>(sink: S, clock: Arc<dyn Clock>) -> Arc<Self> {
         let (tx, rx) = mpsc::channel();
         let tx = Arc::new(tx);
-        let tx2 = Arc::clone(&tx);
 
         let minecraft = Arc::new(Self {
             keybindings:  Mutex::new(KeyBindings::default()),
-            busy: Arc::new(Mutex::new(false)),
+            selected: Arc::new(Mutex::new(None)),
+            stopped: Arc::new(Mutex::new(None)),
+            stats: Arc::new(Stats::new(Arc::clone(&clock))),
+            clock: Arc::clone(&clock),
             tx
         });
 
-        let busy = Arc::clone(&minecraft.busy);
+        let selected = Arc::clone(&minecraft.selected);
+        let stopped = Arc::clone(&minecraft.stopped);
+        let stats = Arc::clone(&minecraft.stats);
+        let participant = Participant::new(&clock);
 
         thread::spawn(move || {
+            let _participant = participant;
+            // waits through the clock, senders notify it
+            let recv = |timeout: Option<Duration>| {
+                let mut received = Err(RecvTimeoutError::Timeout);
+                let deadline = timeout.map(|timeout| clock.now() + timeout);
+                clock.wait_until(deadline, &mut || {
+                    received = rx.try_recv().map_err(|err| match err {
+                        TryRecvError::Empty => RecvTimeoutError::Timeout,
+                        TryRecvError::Disconnected => RecvTimeoutError::Disconnected,
+                    });
+                    !matches!(received, Err(RecvTimeoutError::Timeout))
+                });
+                received
+            };
+
+            let sink = Rc::new(RefCell::new(sink));
             let key_press = Rc::new(RefCell::new(None));
             let button_press = Rc::new(RefCell::new(None));
-            let skip = Rc::new(RefCell::new(None));
+            // messages taken while sending, handled before those still queued
+            let pending = Rc::new(RefCell::new(VecDeque::new()));
 
             let kp2 = Rc::clone(&key_press);
             let bp2 = Rc::clone(&button_press);
-            let sk2 = Rc::clone(&skip);
+            let pe2 = Rc::clone(&pending);
+            let si2 = Rc::clone(&sink);
             let send = |events: Vec<&EventType>| {
+                stats.events(events.len());
                 for event in events {
                     match event {
-                        EventType::KeyPress(key) => *kp2.borrow_mut() = Some(*key),
+                        EventType::KeyPress(key) => {
+                            *kp2.borrow_mut() = Some(*key);
+                            *selected.lock().unwrap() = Some(*key);
+                        },
                         EventType::KeyRelease(_) => *kp2.borrow_mut() = None,
                         EventType::ButtonPress(button) => *bp2.borrow_mut() = Some(*button),
                         EventType::ButtonRelease(_) => *bp2.borrow_mut() = None,
                         _ => unreachable!()
                     }
-                    simulate(event).unwrap_or(());
+                    si2.borrow_mut().send(event);
                 }
-                if let Ok(message) = rx.recv_timeout(Duration::from_millis(30)) {
-                    *sk2.borrow_mut() = Some(message);
-                    return
+                // a press is held for all of `PRESS`, the wait after a
+                // release ends as a message comes in; the first message
+                // arriving is kept, later ones stay queued behind it
+                let held = kp2.borrow().is_some() || bp2.borrow().is_some();
+                let deadline = clock.now() + PRESS;
+                if pe2.borrow().is_empty() {
+                    if let Ok(message) = recv(Some(PRESS)) { pe2.borrow_mut().push_back(message) }
                 }
+                if held { clock.wait_until(Some(deadline), &mut || false); }
             };
 
             let kp2 = Rc::clone(&key_press);
             let bp2 = Rc::clone(&button_press);
+            let si2 = Rc::clone(&sink);
             let release_all = || {
-                if let Some(key) = (*kp2.borrow_mut()).take() { simulate(&EventType::KeyRelease(key)).unwrap_or(()); }
-                if let Some(button) = (*bp2.borrow_mut()).take() { simulate(&EventType::ButtonRelease(button)).unwrap_or(()); }
-                thread::sleep(Duration::from_millis(20));
+                let key = (*kp2.borrow_mut()).take();
+                let button = (*bp2.borrow_mut()).take();
+                // nothing is held between clicks, waiting would only delay the next one
+                if key.is_none() && button.is_none() { return }
+                if let Some(key) = key {
+                    si2.borrow_mut().send(&EventType::KeyRelease(key));
+                    stats.events(1);
+                }
+                if let Some(button) = button {
+                    si2.borrow_mut().send(&EventType::ButtonRelease(button));
+                    stats.events(1);
+                }
+                clock.sleep(Duration::from_millis(20));
             };
 
-            while let Ok(message) = rx.recv() {
+            loop {
+                // a message taken while sending is handled first, it came
+                // before those still queued
+                let message = match pending.borrow_mut().pop_front() {
+                    Some(message) => {
+                        release_all();
+                        message
+                    },
+                    None => match recv(None) {
+                        Ok(message) => message,
+                        Err(_) => break,
+                    },
+                };
                 match message {
-                    Message::UseItem(slot, strong) => {
-                        let mut busy = busy.lock().unwrap();
-                        if !strong && *busy { return }
-                        if strong { *busy = true }
-                        send(vec![&EventType::KeyPress(slot), &EventType::ButtonPress(Button::Right)]);
-                        send(vec![&EventType::KeyRelease(slot), &EventType::ButtonRelease(Button::Right)]);
-                        if strong { *busy = false }
+                    Message::Click(slot, button, phase) => {
+                        if let Some((_, deadline)) = phase {
+                            let now = clock.now();
+                            let stopped = stopped.lock().unwrap().is_some_and(|stopped| deadline <= stopped);
+                            if stopped || now > deadline + STALE {
+                                stats.drop_click();
+                                continue
+                            }
+                            stats.late(now.saturating_sub(deadline));
+                        }
+                        send(vec![&EventType::KeyPress(slot), &EventType::ButtonPress(button)]);
+                        stats.sent(phase.map(|(phase, _)| phase));
+                        send(vec![&EventType::KeyRelease(slot), &EventType::ButtonRelease(button)]);
                     }
-                    Message::PunchItem(slot, strong) => {
-                        let mut busy = busy.lock().unwrap();
-                        if !strong && *busy { return }
-                        if strong { *busy = true }
-                        send(vec![&EventType::KeyPress(slot), &EventType::ButtonPress(Button::Left)]);
-                        send(vec![&EventType::KeyRelease(slot), &EventType::ButtonRelease(Button::Left)]);
-                        if strong { *busy = false }
+                    Message::Custom(slot, action, return_slot, return_delay) => {
+                        stats.custom_use();
+                        let previous = *selected.lock().unwrap();
+                        for step in action.steps(slot) {
+                            let slot = step.slot.unwrap_or(slot);
+                            send(vec![&EventType::KeyPress(slot), &EventType::ButtonPress(step.button)]);
+                            // sending the press held it for `PRESS` already
+                            let hold = Duration::from_millis(step.hold_millis).saturating_sub(PRESS);
+                            if !hold.is_zero() { clock.sleep(hold) }
+                            send(vec![&EventType::KeyRelease(slot), &EventType::ButtonRelease(step.button)]);
+                            if step.delay_millis > 0 { clock.sleep(Duration::from_millis(step.delay_millis)) }
+                        }
+                        // clicks the macro queued meanwhile are stale, sending
+                        // them now would make up for the action in a burst
+                        let queued: Vec<_> = pending.borrow_mut().drain(..)
+                            .chain(iter::from_fn(|| rx.try_recv().ok()))
+                            .collect();
+                        for message in queued {
+                            match message {
+                                Message::Click(..) => stats.drop_click(),
+                                message => pending.borrow_mut().push_back(message),
+                            }
+                        }
+                        let target = match return_slot {
+                            ReturnSlot::Stay => None,
+                            ReturnSlot::Previous => previous,
+                            ReturnSlot::Slot(slot) => Some(slot),
+                        };
+                        // a message arriving meanwhile makes returning pointless
+                        let target = target.filter(|target| *selected.lock().unwrap() != Some(*target));
+                        if let Some(target) = target.filter(|_| pending.borrow().is_empty()) {
+                            match recv(Some(return_delay)) {
+                                Ok(message) => pending.borrow_mut().push_back(message),
+                                Err(_) => send(vec![&EventType::KeyPress(target), &EventType::KeyRelease(target)]),
+                            }
+                        }
+                    }
+                    Message::Select(slot) => {
+                        if *selected.lock().unwrap() != Some(slot) {
+                            send(vec![&EventType::KeyPress(slot), &EventType::KeyRelease(slot)]);
+                        }
                     }
-                }
-                if let Some(message) = (*skip.borrow_mut()).take() {
-                    release_all();
-                    tx2.send(message).unwrap();
                 }
             }
         });
         minecraft
     }
 
-    pub fn use_item(&self, slot: Key) {
-        self.tx.send(Message::UseItem(slot, true)).unwrap();
+    /// Runs `action` on `slot`, interrupting the macro clicks, then selects
+    /// `return_slot` after `return_delay`.
+    pub fn use_item(&self, slot: Key, action: Action, return_slot: ReturnSlot, return_delay: Duration) {
+        self.tx.send(Message::Custom(slot, action, return_slot, return_delay)).unwrap();
+        self.clock.notify();
+    }
+
+    /// Selects `slot` unless it is already held.
+    pub fn select(&self, slot: Key) {
+        self.tx.send(Message::Select(slot)).unwrap();
+        self.clock.notify();
+    }
+
+    /// Clock the worker and the macro driving it are timed on.
+    pub fn clock(&self) -> &Arc<dyn Clock> { &self.clock }
+
+    /// Hotbar slot selected last, `None` before the first selection.
+    pub fn selected_slot(&self) -> Option<Key> {
+        *self.selected.lock().unwrap()
+    }
+
+    /// Selects `slot` and clicks `button` once, yielding to custom item uses.
+    pub fn click(&self, slot: Key, button: Button) {
+        self.tx.send(Message::Click(slot, button, None)).unwrap();
+        self.clock.notify();
     }
-    
-    pub fn use_fishing_rod(&self) {
-        self.tx.send(Message::UseItem(self.keybindings.lock().unwrap().fishing_rod, false)).unwrap();
+
+    /// Like `click`, counting the click for phase `phase` in `stats`. It is
+    /// dropped if it cannot be sent close to `deadline`.
+    pub fn phase_click(&self, phase: usize, slot: Key, button: Button, deadline: Duration) {
+        self.tx.send(Message::Click(slot, button, Some((phase, deadline)))).unwrap();
+        self.clock.notify();
     }
 
-    pub fn use_sword(&self) {
-        self.tx.send(Message::PunchItem(self.keybindings.lock().unwrap().sword, false)).unwrap();
+    /// Drops the phase clicks due until now that are still queued, so the
+    /// macro stops clicking as soon as it is paused.
+    pub fn drop_phase_clicks(&self) {
+        *self.stopped.lock().unwrap() = Some(self.clock.now());
     }
 
     pub fn load_keybindings(&self, keybindings: KeyBindings) {
@@ -146,3 +396,184 @@ impl Minecraft {
         *self.keybindings.lock().unwrap() = KeyBindings::default();
     }
 }
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyboard::{clock::VirtualClock, sink::RecordingSink};
+    use EventType::*;
+
+    /// Worker on a virtual clock, with the sink recording its events.
+    fn worker() -> (Arc<Minecraft>, Arc<VirtualClock>, RecordingSink) {
+        let clock = VirtualClock::new();
+        let sink = RecordingSink::with_clock(clock.clone());
+        let minecraft = Minecraft::with_clock(sink.clone(), clock.clone());
+        clock.settle();
+        (minecraft, clock, sink)
+    }
+
+    fn at(millis: u64, event: EventType) -> (Duration, EventType) {
+        (Duration::from_millis(millis), event)
+    }
+
+    /// Events of `action` run on slot 3 while slot 1 is selected.
+    fn custom_events(action: Action) -> Vec<(Duration, EventType)> {
+        let (minecraft, clock, sink) = worker();
+        minecraft.select(Key::Num1);
+        minecraft.use_item(Key::Num3, action, ReturnSlot::Previous, Duration::from_millis(100));
+        clock.advance(Duration::from_secs(1));
+        sink.events()
+    }
+
+    #[test]
+    fn phase_click() {
+        let (minecraft, clock, sink) = worker();
+        minecraft.phase_click(0, Key::Num1, Button::Left, clock.now());
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(sink.events(), [
+            at(0, KeyPress(Key::Num1)), at(0, ButtonPress(Button::Left)),
+            at(30, KeyRelease(Key::Num1)), at(30, ButtonRelease(Button::Left)),
+        ]);
+        assert_eq!(minecraft.stats.snapshot().phases[0].sent, 1);
+    }
+
+    #[test]
+    fn sent_intervals() {
+        let (minecraft, clock, _sink) = worker();
+        for wait in [100, 150, 0] {
+            minecraft.phase_click(0, Key::Num1, Button::Left, clock.now());
+            clock.advance(Duration::from_millis(wait));
+        }
+        clock.advance(Duration::from_secs(1));
+        let phase = &minecraft.stats.snapshot().phases[0];
+        assert_eq!((phase.sent, phase.mean_micros, phase.stddev_micros), (3, 125_000.0, 25_000.0));
+    }
+
+    #[test]
+    fn late_clicks_dropped() {
+        let (minecraft, clock, sink) = worker();
+        for _ in 0..6 { minecraft.phase_click(0, Key::Num1, Button::Left, Duration::ZERO) }
+        clock.advance(Duration::from_secs(1));
+        // every click holds its button, so the queue falls behind
+        let presses: Vec<_> = sink.events().into_iter()
+            .filter(|(_, event)| *event == ButtonPress(Button::Left))
+            .map(|(time, _)| time.as_millis())
+            .collect();
+        assert_eq!(presses, [0, 30, 60, 90]);
+        let stats = minecraft.stats.snapshot();
+        assert_eq!((stats.dropped, stats.late_max_micros), (2, 90_000.0));
+    }
+
+    #[test]
+    fn stopped_clicks_dropped() {
+        let (minecraft, clock, sink) = worker();
+        for _ in 0..3 { minecraft.phase_click(0, Key::Num1, Button::Left, clock.now()) }
+        clock.settle();
+        minecraft.drop_phase_clicks();
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(sink.events().len(), 4);
+        assert_eq!(minecraft.stats.snapshot().dropped, 2);
+
+        // clicks due later go out
+        minecraft.phase_click(0, Key::Num1, Button::Left, clock.now());
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(sink.events().len(), 8);
+    }
+
+    #[test]
+    fn select_only_once() {
+        let (minecraft, clock, sink) = worker();
+        minecraft.select(Key::Num2);
+        minecraft.select(Key::Num2);
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(sink.events(), [at(0, KeyPress(Key::Num2)), at(0, KeyRelease(Key::Num2))]);
+    }
+
+    #[test]
+    fn use_and_punch() {
+        for (action, button) in [(Action::Use, Button::Right), (Action::Punch, Button::Left)] {
+            assert_eq!(custom_events(action), [
+                at(0, KeyPress(Key::Num1)), at(0, KeyRelease(Key::Num1)),
+                at(0, KeyPress(Key::Num3)), at(0, ButtonPress(button)),
+                at(30, KeyRelease(Key::Num3)), at(30, ButtonRelease(button)),
+                // back to the previous slot after the return delay
+                at(160, KeyPress(Key::Num1)), at(160, KeyRelease(Key::Num1)),
+            ]);
+        }
+    }
+
+    #[test]
+    fn hold() {
+        assert_eq!(custom_events(Action::Hold { millis: 200 }), [
+            at(0, KeyPress(Key::Num1)), at(0, KeyRelease(Key::Num1)),
+            at(0, KeyPress(Key::Num3)), at(0, ButtonPress(Button::Right)),
+            at(200, KeyRelease(Key::Num3)), at(200, ButtonRelease(Button::Right)),
+            at(330, KeyPress(Key::Num1)), at(330, KeyRelease(Key::Num1)),
+        ]);
+    }
+
+    #[test]
+    fn double_use() {
+        assert_eq!(custom_events(Action::DoubleUse), [
+            at(0, KeyPress(Key::Num1)), at(0, KeyRelease(Key::Num1)),
+            at(0, KeyPress(Key::Num3)), at(0, ButtonPress(Button::Right)),
+            at(30, KeyRelease(Key::Num3)), at(30, ButtonRelease(Button::Right)),
+            at(60, KeyPress(Key::Num3)), at(60, ButtonPress(Button::Right)),
+            at(90, KeyRelease(Key::Num3)), at(90, ButtonRelease(Button::Right)),
+            at(220, KeyPress(Key::Num1)), at(220, KeyRelease(Key::Num1)),
+        ]);
+    }
+
+    #[test]
+    fn sequence() {
+        let steps = vec![
+            Step { slot: Some(Key::Num7), button: Button::Left, hold_millis: 10, delay_millis: 40 },
+            Step::click(Button::Right),
+        ];
+        assert_eq!(custom_events(Action::Sequence(steps)), [
+            at(0, KeyPress(Key::Num1)), at(0, KeyRelease(Key::Num1)),
+            at(0, KeyPress(Key::Num7)), at(0, ButtonPress(Button::Left)),
+            // held for the least time a click takes
+            at(30, KeyRelease(Key::Num7)), at(30, ButtonRelease(Button::Left)),
+            // a step without a slot uses the one of the binding
+            at(100, KeyPress(Key::Num3)), at(100, ButtonPress(Button::Right)),
+            at(130, KeyRelease(Key::Num3)), at(130, ButtonRelease(Button::Right)),
+            at(260, KeyPress(Key::Num1)), at(260, KeyRelease(Key::Num1)),
+        ]);
+    }
+
+    #[test]
+    fn stale_clicks_dropped() {
+        let (minecraft, clock, sink) = worker();
+        minecraft.use_item(Key::Num3, Action::Hold { millis: 200 }, ReturnSlot::Stay, Duration::ZERO);
+        for _ in 0..3 {
+            clock.advance(Duration::from_millis(50));
+            minecraft.phase_click(0, Key::Num1, Button::Left, clock.now());
+        }
+        clock.advance(Duration::from_millis(100));
+        assert_eq!(sink.events(), [
+            at(0, KeyPress(Key::Num3)), at(0, ButtonPress(Button::Right)),
+            at(200, KeyRelease(Key::Num3)), at(200, ButtonRelease(Button::Right)),
+        ]);
+
+        // clicks after the action go out again
+        minecraft.phase_click(0, Key::Num1, Button::Left, clock.now());
+        clock.advance(Duration::from_millis(100));
+        assert_eq!(sink.events()[4..], [at(250, KeyPress(Key::Num1)), at(250, ButtonPress(Button::Left)),
+            at(280, KeyRelease(Key::Num1)), at(280, ButtonRelease(Button::Left))]);
+        let stats = minecraft.stats.snapshot();
+        assert_eq!((stats.dropped, stats.phases[0].sent), (3, 1));
+    }
+
+    #[test]
+    fn stay_on_used_slot() {
+        let (minecraft, clock, sink) = worker();
+        minecraft.select(Key::Num1);
+        minecraft.use_item(Key::Num3, Action::Use, ReturnSlot::Stay, Duration::from_millis(100));
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(sink.events().last(), Some(&at(30, ButtonRelease(Button::Right))));
+        assert_eq!(minecraft.selected_slot(), Some(Key::Num3));
+    }
+}
@@ -1,22 +1,78 @@
 mod listener;
+pub mod error;
+pub mod format;
+pub mod binding;
+pub mod clock;
+/// Unix-domain sockets are not available on other platforms.
+#[cfg(unix)]
+pub mod control;
 pub mod run;
 pub mod minecraft;
+pub mod profile;
+pub mod saver;
+pub mod schema;
+pub mod share;
+pub mod sink;
+pub mod source;
+pub mod stats;
 
 pub use listener::Listener;
 pub use run::MacroService;
+pub use binding::{Binding, Input, Modifiers};
+pub use clock::{Clock, RealClock, VirtualClock};
+pub use error::{LoadError, Fallback};
+pub use format::Format;
+pub use share::SharedProfile;
+pub use sink::{InputSink, RdevSink, RecordingSink};
+pub use source::{EventSource, RdevSource, ChannelSource};
+pub use stats::{Stats, Snapshot};
 
-use std::io;
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
 
-pub trait SaveJson {
-    fn from_json(json: &mut dyn io::Read) -> Self where Self: Sized + Default, for<'de> Self: Deserialize<'de> {
-        serde_json::from_reader(json).unwrap_or(Self::default())
+/// Files stored as json or toml stamped with `schema::VERSION`.
+pub trait SaveJson: Serialize + DeserializeOwned + Default {
+    /// Upgrades `value` written by an older version, returning whether it
+    /// changed.
+    fn migrate(value: &mut Value) -> bool;
+
+    /// Parses `text` of `format` written by this or an older version.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` with the position and field of the first error if the
+    /// file is not valid or does not match.
+    fn from_text(text: &str, format: Format) -> Result<Self, LoadError> {
+        let mut value = format.parse(text)?;
+        let migrated = Self::migrate(&mut value);
+        Self::from_value(text, format, value, migrated)
+    }
+
+    /// Deserializes `value` parsed from `text`. Errors are located in `text`
+    /// unless the value was `migrated`, as positions no longer match then.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if the value does not match.
+    fn from_value(text: &str, format: Format, value: Value, migrated: bool) -> Result<Self, LoadError> {
+        if migrated { format::from_migrated(value) } else { format.deserialize(text) }
     }
 
-    fn to_json(&self, writer: &mut impl io::Write) -> Result<(), serde_json::Error> where Self: Serialize {
-        serde_json::to_writer(writer, &self)
+    /// Serializes into `format`, keeping comments of the `previous` text
+    /// of the file where the format allows.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if the value cannot be represented in `format`.
+    fn to_text(&self, format: Format, previous: Option<&str>) -> Result<String, String> {
+        format.serialize(self, previous)
     }
 }
 
-impl SaveJson for minecraft::KeyBindings { }
-impl SaveJson for run::Settings { }
+impl SaveJson for minecraft::KeyBindings {
+    fn migrate(value: &mut Value) -> bool { schema::migrate_profile(None, Some(value))[1] }
+}
+
+impl SaveJson for run::Settings {
+    fn migrate(value: &mut Value) -> bool { schema::migrate_profile(Some(value), None)[0] }
+}
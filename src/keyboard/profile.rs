@@ -0,0 +1,209 @@
+use std::{
+    fs, io::{self, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use super::format::Format;
+
+
+
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Files making up a profile without their extension, in
+/// `[settings, keybindings]` order.
+pub const FILES: [&str; 2] = ["settings", "keybindings"];
+
+const ACTIVE_FILE: &str = "active_profile";
+
+/// Previous versions kept of each profile file.
+const BACKUPS: usize = 3;
+
+
+/// Data directory, see [`crate::data_dir`].
+fn data_dir() -> io::Result<PathBuf> { crate::data_dir().map_err(io::Error::other) }
+
+pub fn profiles_dir() -> io::Result<PathBuf> { Ok(data_dir()?.join("profiles")) }
+
+pub fn profile_dir(name: &str) -> io::Result<PathBuf> { Ok(profiles_dir()?.join(name)) }
+
+pub fn backups_dir() -> io::Result<PathBuf> { Ok(data_dir()?.join("backups")) }
+
+pub fn exists(name: &str) -> bool { profile_dir(name).is_ok_and(|dir| dir.is_dir()) }
+
+/// Format `file` of profile `name` is stored in, JSON unless a TOML file
+/// exists.
+pub fn format_of(name: &str, file: &str) -> Format {
+    match profile_dir(name).is_ok_and(|dir| dir.join(Format::Toml.file_name(file)).is_file()) {
+        true => Format::Toml,
+        false => Format::Json,
+    }
+}
+
+/// Path of `file` of profile `name` in the format it is stored in.
+pub fn path(name: &str, file: &str) -> io::Result<PathBuf> {
+    Ok(profile_dir(name)?.join(format_of(name, file).file_name(file)))
+}
+
+/// Lists profile names in alphabetical order.
+pub fn list() -> Vec<String> {
+    let mut profiles: Vec<_> = profiles_dir().and_then(fs::read_dir)
+        .map(|entries| entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().is_dir())
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .collect()
+        )
+        .unwrap_or_default();
+    profiles.sort();
+    profiles
+}
+
+/// Checks whether `name` can be used as a profile name.
+///
+/// # Errors
+///
+/// Returns `Err` if name is empty, too long or contains characters other
+/// than letters, digits, spaces, `-` and `.`.
+pub fn validate_name(name: &str) -> Result<(), String> {
+    if name.trim().is_empty() { return Err(String::from("Profile name is empty")) }
+    if name.trim() != name { return Err(String::from("Profile name has leading or trailing spaces")) }
+    if name.chars().count() > 64 { return Err(String::from("Profile name is too long")) }
+    if name.starts_with('.') { return Err(String::from("Profile name starts with a dot")) }
+    if !name.chars().all(|c| c.is_alphanumeric() || matches!(c, ' ' | '-' | '.')) {
+        return Err(format!("Profile name {:?} contains invalid characters", name))
+    }
+    Ok(())
+}
+
+fn io_err(err: io::Error) -> String { err.to_string() }
+
+/// Creates an empty profile directory.
+///
+/// # Errors
+///
+/// Returns `Err` if name is invalid or the profile already exists.
+pub fn create(name: &str) -> Result<(), String> {
+    validate_name(name)?;
+    if exists(name) { return Err(format!("Profile {:?} already exists", name)) }
+    fs::create_dir_all(profile_dir(name).map_err(io_err)?).map_err(io_err)
+}
+
+/// Copies files of profile `from` into a new profile `to`.
+///
+/// # Errors
+///
+/// Returns `Err` if `from` does not exist or `to` cannot be created.
+pub fn duplicate(from: &str, to: &str) -> Result<(), String> {
+    if !exists(from) { return Err(format!("Profile {:?} does not exist", from)) }
+    create(to)?;
+    for file in FILES {
+        let file = format_of(from, file).file_name(file);
+        let source = profile_dir(from).map_err(io_err)?.join(&file);
+        if source.is_file() {
+            fs::copy(source, profile_dir(to).map_err(io_err)?.join(file)).map_err(io_err)?;
+        }
+    }
+    Ok(())
+}
+
+/// # Errors
+///
+/// Returns `Err` if `from` does not exist or `to` is invalid or taken.
+pub fn rename(from: &str, to: &str) -> Result<(), String> {
+    if !exists(from) { return Err(format!("Profile {:?} does not exist", from)) }
+    validate_name(to)?;
+    if exists(to) { return Err(format!("Profile {:?} already exists", to)) }
+    fs::rename(profile_dir(from).map_err(io_err)?, profile_dir(to).map_err(io_err)?).map_err(io_err)
+}
+
+/// # Errors
+///
+/// Returns `Err` if the profile does not exist or cannot be removed.
+pub fn delete(name: &str) -> Result<(), String> {
+    if !exists(name) { return Err(format!("Profile {:?} does not exist", name)) }
+    fs::remove_dir_all(profile_dir(name).map_err(io_err)?).map_err(io_err)
+}
+
+/// Reads name of the profile selected in the last session.
+pub fn read_active() -> Option<String> {
+    let name = fs::read_to_string(data_dir().ok()?.join(ACTIVE_FILE)).ok()?;
+    let name = name.trim();
+    validate_name(name).ok()?;
+    Some(String::from(name))
+}
+
+pub fn write_active(name: &str) -> io::Result<()> {
+    fs::write(data_dir()?.join(ACTIVE_FILE), name)
+}
+
+/// Moves configuration files of versions without profiles into the
+/// default profile, if no profile exists yet.
+pub fn migrate_legacy() -> io::Result<()> {
+    if !list().is_empty() { return Ok(()) }
+
+    let dir = profile_dir(DEFAULT_PROFILE)?;
+    fs::create_dir_all(&dir)?;
+    for file in FILES.map(|file| Format::Json.file_name(file)) {
+        let legacy = data_dir()?.join(&file);
+        if legacy.is_file() {
+            fs::rename(legacy, dir.join(file))?;
+        }
+    }
+    Ok(())
+}
+
+/// Copies `file` of profile `name`, with its extension, into the backups
+/// folder named after the profile and the current unix time, and returns
+/// the path of the copy.
+pub fn backup(name: &str, file: &str) -> io::Result<PathBuf> {
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or(0);
+    fs::create_dir_all(backups_dir()?)?;
+    let path = backups_dir()?.join(format!("{}.{}.{}", name, time, file));
+    fs::copy(profile_dir(name)?.join(file), &path)?;
+    Ok(path)
+}
+
+/// Replaces `file` of profile `name`, with its extension, by `contents`. The new contents are
+/// written next to it first and renamed over it, so a crash never leaves a
+/// half written file behind.
+///
+/// The replaced file is kept as the newest rotating backup if it is valid.
+pub fn write(name: &str, file: &str, contents: &[u8]) -> io::Result<()> {
+    let dir = profile_dir(name)?;
+    let path = dir.join(file);
+    let temp = dir.join(format!("{}.tmp", file));
+    fs::create_dir_all(&dir)?;
+
+    let mut writer = fs::File::create(&temp)?;
+    writer.write_all(contents)?;
+    writer.sync_all()?;
+    drop(writer);
+
+    let previous = fs::read(&path).ok()
+        .filter(|previous| previous != contents)
+        .filter(|previous| is_valid(file, previous));
+    if previous.is_some() { rotate_backups(name, file)? }
+    fs::rename(temp, path)
+}
+
+/// Whether `contents` of `file` can be parsed in the format of its extension.
+fn is_valid(file: &str, contents: &[u8]) -> bool {
+    let Ok(contents) = std::str::from_utf8(contents) else { return false };
+    Format::of(Path::new(file)).is_none_or(|format| format.parse(contents).is_ok())
+}
+
+/// Shifts the numbered backups of `file` and copies the current file as the
+/// first one, dropping the oldest.
+fn rotate_backups(name: &str, file: &str) -> io::Result<()> {
+    let backups = backups_dir()?;
+    let backup = |i: usize| backups.join(format!("{}.bak{}.{}", name, i, file));
+    fs::create_dir_all(&backups)?;
+    for i in (1..BACKUPS).rev() {
+        if backup(i).is_file() { fs::rename(backup(i), backup(i + 1))? }
+    }
+    fs::copy(profile_dir(name)?.join(file), backup(1)).map(|_| ())
+}
@@ -1,48 +1,220 @@
 use std::{
-     sync::{Arc, Condvar, Mutex},
-     thread, time::Duration
+     mem,
+     sync::{mpsc, Arc, Mutex},
+     thread, time::Duration,
 };
 
-use rand::{thread_rng, Rng};
+use rand::{seq::SliceRandom, thread_rng, Rng, RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rand_distr::Normal;
+use rdev::{Key, Button};
 
 use serde::{Serialize, Deserialize};
 
-use super::minecraft::Minecraft;
+use super::{
+    clock::{Clock, Participant, RealClock},
+    minecraft::{Minecraft, Action},
+};
+
+
+
+/// Wait between checks for new settings while there is no phase to run.
+const IDLE: Duration = Duration::from_millis(100);
+
+/// Default bounds of the waits of a phase, wide so the interval can be
+/// changed without being clamped. The minimum leaves the worker time for
+/// each click, see `minecraft::PRESS`.
+pub const MIN_MICROS: u64 = 40_000;
+pub const MAX_MICROS: u64 = 1_000_000;
 
+/// Largest seed, as TOML files only hold signed integers.
+pub const MAX_SEED: u64 = i64::MAX as u64;
 
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq)]
 enum Message {
-    None, Skip, Abort, Start, Stop,
+    None, Skip, Abort, Stop,
+    /// Seed of the run, `None` if an injected generator is used.
+    Start(Option<u64>),
 }
 
 
 
-#[derive(Serialize, Deserialize, Debug)]
+/// One step of the macro cycle: select `slot` and click `button` `count` times.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Phase {
+    pub slot: Key,
+    pub button: Button,
+    pub count: u64,
+    pub sleep_micros: u64,
+    /// How the wait before each click is picked around `sleep_micros`.
+    #[serde(default)]
+    pub distribution: Distribution,
+    /// Bounds every picked wait is clamped to, whatever the distribution.
+    #[serde(default = "min_micros")]
+    pub min_micros: u64,
+    #[serde(default = "max_micros")]
+    pub max_micros: u64,
+}
+
+fn min_micros() -> u64 { MIN_MICROS }
+
+fn max_micros() -> u64 { MAX_MICROS }
+
+impl Phase {
+    pub fn sword() -> Self {
+        Self {
+            slot: Key::Num1,
+            button: Button::Left,
+            count: 7,
+            sleep_micros: 66_666,
+            distribution: Distribution::default(),
+            min_micros: MIN_MICROS,
+            max_micros: MAX_MICROS,
+        }
+    }
+
+    pub fn fishing_rod() -> Self {
+        Self {
+            slot: Key::Num2,
+            button: Button::Right,
+            count: 5,
+            sleep_micros: 50_000,
+            distribution: Distribution::default(),
+            min_micros: MIN_MICROS,
+            max_micros: MAX_MICROS,
+        }
+    }
+
+    /// Picks the wait before the next click, within the bounds. `max_micros`
+    /// wins if the bounds are swapped, and a ratio that is not finite picks
+    /// `sleep_micros`.
+    pub fn interval(&self, rng: &mut impl Rng) -> Duration {
+        let base = self.sleep_micros as f64;
+        let micros = match &self.distribution {
+            Distribution::Fixed => base,
+            // scaled after sampling, as a range this wide could not be sampled
+            Distribution::Uniform { ratio } if ratio.is_finite() => {
+                base * (1.0 + ratio.abs() * rng.gen_range(-1.0..=1.0))
+            },
+            Distribution::Normal { ratio } if ratio.is_finite() => match Normal::new(base, base * ratio.abs()) {
+                Ok(normal) => rng.sample(normal),
+                Err(_) => base,
+            },
+            Distribution::Uniform { .. } | Distribution::Normal { .. } => base,
+            Distribution::Table { micros } => micros.choose(rng).map_or(base, |micros| *micros as f64),
+        };
+        // negative samples of the normal distribution saturate to zero
+        Duration::from_micros((micros.round() as u64).max(self.min_micros).min(self.max_micros))
+    }
+
+    /// Wait the distribution is centered on, the mean of the table for one.
+    pub fn nominal_micros(&self) -> u64 {
+        match &self.distribution {
+            Distribution::Table { micros } if !micros.is_empty() => {
+                (micros.iter().map(|micros| u128::from(*micros)).sum::<u128>() / micros.len() as u128) as u64
+            },
+            _ => self.sleep_micros,
+        }
+    }
+}
+
+
+/// How the waits between clicks of a phase are picked.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Distribution {
+    /// Always `sleep_micros`.
+    Fixed,
+    /// `sleep_micros` scaled by a factor in `[1 - ratio, 1 + ratio]`.
+    Uniform { ratio: f64 },
+    /// Normal around `sleep_micros`, deviating by `ratio` times it.
+    Normal { ratio: f64 },
+    /// One of the given waits at random, `sleep_micros` if there is none.
+    Table { micros: Vec<u64> },
+}
+
+impl Default for Distribution {
+    fn default() -> Self { Distribution::Uniform { ratio: 0.2 } }
+}
+
+
+/// Slot selected once a custom item use is done.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum ReturnSlot {
+    /// Stays on the used slot.
+    Stay,
+    /// Goes back to the slot held before the use.
+    #[default]
+    Previous,
+    Slot(Key),
+}
+
+
+/// Phases run in order, the cycle repeats until the macro is paused.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
 pub struct Settings {
-    pub sleep_micros: [u64; 2],
-    pub count: [u64; 2],
-    pub random_ratio: f64,
+    pub phases: Vec<Phase>,
+    pub return_slot: ReturnSlot,
+    /// Wait after a custom item use before returning to `return_slot`.
+    pub return_delay_millis: u64,
+    /// Seed of the intervals picked, so runs can be repeated exactly. A new
+    /// one is drawn on every start if not set.
+    pub seed: Option<u64>,
 }
 
 impl Default for Settings {
     fn default() -> Self {
         Self {
-            sleep_micros: [66_666, 50_000],
-            count: [7, 5],
-            random_ratio: 0.2,
+            phases: vec![Phase::sword(), Phase::fishing_rod()],
+            return_slot: ReturnSlot::Previous,
+            return_delay_millis: 100,
+            seed: None,
         }
     }
 }
 
+impl Settings {
+    /// Slot of the first left clicking phase, or of the first phase if none.
+    pub fn sword_slot(&self) -> Option<Key> {
+        self.phases.iter()
+            .find(|phase| phase.button == Button::Left)
+            .or(self.phases.first())
+            .map(|phase| phase.slot)
+    }
+
+    /// Checks values the types allow but the macro cannot run with.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` naming the first field with a ratio that is not a
+    /// finite number.
+    pub fn validate(&self) -> Result<(), String> {
+        for (i, phase) in self.phases.iter().enumerate() {
+            if let Distribution::Uniform { ratio } | Distribution::Normal { ratio } = phase.distribution {
+                if !ratio.is_finite() {
+                    return Err(format!("phases.{}.distribution: ratio must be a finite number", i))
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
 
 
 pub struct MacroService {
     pub settings: Arc<Mutex<Settings>>,
     running: Mutex<bool>,
     initialized: Mutex<bool>,
-    park: (Mutex<Message>, Condvar),
+    /// Message for the macro thread, which waits for it through `clock`.
+    park: Mutex<Message>,
     minecraft: Option<Arc<Minecraft>>,
+    clock: Arc<dyn Clock>,
+    /// Generator set with `set_rng`, used instead of a seeded one.
+    rng: Mutex<Option<Box<dyn RngCore + Send>>>,
+    /// Receives the seed of every run, see `seeds`.
+    seeds: Mutex<Option<mpsc::Sender<u64>>>,
 }
 
 impl Default for MacroService {
@@ -51,8 +223,11 @@ impl Default for MacroService {
             settings: Arc::new(Mutex::new(Settings::default())),
             running: Mutex::new(false),
             initialized: Mutex::new(false),
-            park: (Mutex::new(Message::None), Condvar::new()),
+            park: Mutex::new(Message::None),
             minecraft: None,
+            clock: RealClock::new(),
+            rng: Mutex::new(None),
+            seeds: Mutex::new(None),
         }
     }
 }
@@ -60,21 +235,49 @@ impl Default for MacroService {
 impl MacroService {
     pub fn new(minecraft: Arc<Minecraft>) -> Arc<Self> {
         Arc::new(Self {
+            clock: Arc::clone(minecraft.clock()),
             minecraft: Some(minecraft),
             ..Self::default()
         })
     }
 
-    fn sleep(&self, micros: u64, run: impl Fn() -> ()) -> Message {
-        let (lock, cvar) = &self.park;
-        let (message, result) = cvar.wait_timeout(lock.lock().unwrap(), Duration::from_micros(micros)).unwrap();
-        if !result.timed_out() { return *message; }
-        run();
+    /// Waits until `deadline` on the clock, returning early with a message
+    /// sent meanwhile. Returns `Message::None` once the deadline passes,
+    /// waits for a message only if there is no deadline.
+    fn sleep_until(&self, deadline: Option<Duration>) -> Message {
+        let mut message = Message::None;
+        self.clock.wait_until(deadline, &mut || {
+            // taken, so it is never handled twice
+            message = mem::replace(&mut *self.park.lock().unwrap(), Message::None);
+            message != Message::None
+        });
+        message
+    }
 
-        Message::None
+    /// Picks intervals with `rng` from now on, in place of a generator seeded
+    /// on every start.
+    pub fn set_rng(&self, rng: impl RngCore + Send + 'static) {
+        *self.rng.lock().unwrap() = Some(Box::new(rng));
     }
 
-    fn is_running(&self) -> bool { *self.running.lock().unwrap() }
+    /// Picks the next interval of `phase`, with the injected generator if
+    /// there is one.
+    fn interval(&self, phase: &Phase, seeded: &mut ChaCha8Rng) -> Duration {
+        match self.rng.lock().unwrap().as_mut() {
+            Some(rng) => phase.interval(rng),
+            None => phase.interval(seeded),
+        }
+    }
+
+    /// Returns a receiver of the seed of every run `start` begins from now
+    /// on, drawn or set, replacing the previous one.
+    pub fn seeds(&self) -> mpsc::Receiver<u64> {
+        let (tx, rx) = mpsc::channel();
+        *self.seeds.lock().unwrap() = Some(tx);
+        rx
+    }
+
+    pub fn is_running(&self) -> bool { *self.running.lock().unwrap() }
 
     fn is_initialized(&self) -> bool { *self.initialized.lock().unwrap() }
 
@@ -92,41 +295,67 @@ impl MacroService {
         *initialized = true;
 
         let listener = Arc::clone(&self);
+        let participant = Participant::new(&self.clock);
         thread::spawn(move || {
-            let mut rng = thread_rng();
-            
+            let _participant = participant;
+            // seeded again on every start
+            // a fixed algorithm, so seeds replay on every platform and version
+            let mut rng = ChaCha8Rng::seed_from_u64(0);
+
             'outer: loop {
-                let (lock, cvar) = &listener.park;
-                let message = *cvar.wait(lock.lock().unwrap()).unwrap();
-                match message {
-                    Message::Start => {
+                match listener.sleep_until(None) {
+                    Message::Start(seed) => {
                         *listener.running.lock().unwrap() = true;
+                        if let Some(seed) = seed { rng = ChaCha8Rng::seed_from_u64(seed) }
+                        // clicks are due at fixed points from the start, so
+                        // time spent between waits does not add up
+                        let mut deadline = listener.clock.now();
                         'inner: loop {
-                            for i in 0..2 {
-                                let settings = listener.settings.lock().unwrap();
-                                for _ in 0..=(settings.count[i] + 1){
-                                    match listener.sleep(((1.0 + rng.gen_range(-settings.random_ratio..=settings.random_ratio)) * settings.sleep_micros[i] as f64).round() as u64, || {
-                                        if i == 0 {
-                                            listener.minecraft.as_ref().unwrap().use_sword();
-                                        } else {
-                                            listener.minecraft.as_ref().unwrap().use_fishing_rod();
-                                        }
-                                    }) {
+                            // settings are taken once per cycle, so edits apply on the next one
+                            let phases = listener.settings.lock().unwrap().phases.clone();
+                            if phases.is_empty() {
+                                deadline = listener.clock.now() + IDLE;
+                                match listener.sleep_until(Some(deadline)) {
+                                    Message::Stop => {
+                                        *listener.running.lock().unwrap() = false;
+                                        break 'inner
+                                    },
+                                    Message::Abort => break 'outer,
+                                    _ => continue 'inner,
+                                }
+                            }
+
+                            let minecraft = listener.minecraft.as_ref().unwrap();
+                            for (i, phase) in phases.iter().enumerate() {
+                                minecraft.stats.start_phase(i, phase.nominal_micros());
+                                for _ in 0..=(phase.count + 1){
+                                    let interval = listener.interval(phase, &mut rng);
+                                    deadline += interval;
+                                    // far behind, e.g. after the system slept, clicks are not made up in a burst
+                                    let now = listener.clock.now();
+                                    if now > deadline + interval { deadline = now }
+
+                                    match listener.sleep_until(Some(deadline)) {
+                                        Message::None => {
+                                            minecraft.stats.request(i);
+                                            minecraft.phase_click(i, phase.slot, phase.button, deadline);
+                                        },
                                         Message::Stop => {
                                             *listener.running.lock().unwrap() = false;
                                             break 'inner
                                         },
-                                        Message::Skip => (),
+                                        Message::Skip => minecraft.stats.skip(),
                                         Message::Abort => break 'outer,
-                                        Message::None => (),
-                                        _ => unreachable!(),
+                                        Message::Start(_) => (),
                                     }
                                 }
                             }
+                            minecraft.stats.cycle();
                         }
                     },
                     Message::Abort => { break 'outer },
-                    _ => unreachable!(),
+                    // sent just as the macro stopped
+                    Message::Skip | Message::Stop | Message::None => (),
                 }
             }
             *listener.running.lock().unwrap() = false;
@@ -137,9 +366,8 @@ impl MacroService {
     }
 
     fn notify_thread(&self, msg: Message) {
-        let (lock, cvar) = &self.park;
-        *lock.lock().unwrap() = msg;
-        cvar.notify_one();
+        *self.park.lock().unwrap() = msg;
+        self.clock.notify();
     }
 
     /// Aborts macro thread.
@@ -164,6 +392,11 @@ impl MacroService {
         if !self.is_running() { return Err(String::from("Macro is not running")); }
 
         self.notify_thread(Message::Stop);
+        let minecraft = self.minecraft.as_ref().unwrap();
+        minecraft.drop_phase_clicks();
+        if let Some(slot) = self.settings.lock().unwrap().sword_slot() {
+            minecraft.select(slot);
+        }
         Ok(())
     }
 
@@ -176,15 +409,23 @@ impl MacroService {
         if !self.is_initialized() { return Err(String::from("Macro is not initialized")); }
         if self.is_running() { return Err(String::from("Macro is already running")); }
 
-        self.notify_thread(Message::Start);
+        // drawn here, so it is known once the macro is started
+        let seed = match *self.rng.lock().unwrap() {
+            Some(_) => None,
+            None => Some(self.settings.lock().unwrap().seed.unwrap_or_else(|| thread_rng().gen_range(0..=MAX_SEED))),
+        };
+        if let Some(minecraft) = &self.minecraft { minecraft.stats.seed(seed) }
+        if let (Some(seed), Some(tx)) = (seed, &*self.seeds.lock().unwrap()) { tx.send(seed).unwrap_or(()) }
+        self.notify_thread(Message::Start(seed));
         Ok(())
     }
 
-    pub fn use_item(&self, slot: rdev::Key) {
+    pub fn use_item(&self, slot: rdev::Key, action: Action) {
         if self.is_running() {
             self.notify_thread(Message::Skip);
         }
-        self.minecraft.as_ref().unwrap().use_item(slot);
+        let settings = self.settings.lock().unwrap();
+        self.minecraft.as_ref().unwrap().use_item(slot, action, settings.return_slot, Duration::from_millis(settings.return_delay_millis));
     }
 
     pub fn load_settings(&self, settings: Settings) {
@@ -195,3 +436,118 @@ impl MacroService {
         *self.settings.lock().unwrap() = Settings::default();
     }
 }
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyboard::{clock::VirtualClock, sink::RecordingSink};
+    use rdev::EventType::{self, *};
+
+    fn at(millis: u64, event: EventType) -> (Duration, EventType) {
+        (Duration::from_millis(millis), event)
+    }
+
+    #[test]
+    fn cycle() {
+        let clock = VirtualClock::new();
+        let sink = RecordingSink::with_clock(clock.clone());
+        let minecraft = Minecraft::with_clock(sink.clone(), clock.clone());
+        let service = MacroService::new(Arc::clone(&minecraft));
+        let fixed = |phase: Phase, count, sleep_micros| Phase {
+            count,
+            sleep_micros,
+            distribution: Distribution::Fixed,
+            min_micros: 0,
+            max_micros: 100_000,
+            ..phase
+        };
+        service.load_settings(Settings {
+            phases: vec![fixed(Phase::sword(), 2, 50_000), fixed(Phase::fishing_rod(), 1, 40_000)],
+            ..Settings::default()
+        });
+        Arc::clone(&service).init().unwrap();
+        service.start().unwrap();
+        clock.settle();
+
+        // every phase clicks twice more than its count, the next cycle
+        // starts at 370 ms
+        clock.advance(Duration::from_millis(360));
+        let sword = |millis| [
+            at(millis, KeyPress(Key::Num1)), at(millis, ButtonPress(Button::Left)),
+            at(millis + 30, KeyRelease(Key::Num1)), at(millis + 30, ButtonRelease(Button::Left)),
+        ];
+        let rod = |millis| [
+            at(millis, KeyPress(Key::Num2)), at(millis, ButtonPress(Button::Right)),
+            at(millis + 30, KeyRelease(Key::Num2)), at(millis + 30, ButtonRelease(Button::Right)),
+        ];
+        let expected: Vec<_> = [50, 100, 150, 200].into_iter().flat_map(sword)
+            .chain([240, 280, 320].into_iter().flat_map(rod))
+            .collect();
+        assert_eq!(sink.events(), expected);
+
+        let stats = minecraft.stats.snapshot();
+        assert_eq!(stats.cycles, 1);
+        assert_eq!((stats.phases[0].sent, stats.phases[0].mean_micros), (4, 50_000.0));
+        assert_eq!((stats.phases[1].sent, stats.phases[1].mean_micros), (3, 40_000.0));
+    }
+
+    #[test]
+    fn seeds() {
+        let clock = VirtualClock::new();
+        let minecraft = Minecraft::with_clock(RecordingSink::with_clock(clock.clone()), clock.clone());
+        let service = MacroService::new(Arc::clone(&minecraft));
+        let seeds = service.seeds();
+        Arc::clone(&service).init().unwrap();
+        service.start().unwrap();
+        clock.settle();
+
+        let seed = seeds.try_recv().unwrap();
+        assert!(seed <= MAX_SEED);
+        assert_eq!(minecraft.stats.snapshot().seed, Some(seed));
+
+        // a configured seed is sent as well
+        service.pause().unwrap();
+        clock.settle();
+        service.settings.lock().unwrap().seed = Some(42);
+        service.start().unwrap();
+        clock.settle();
+        assert_eq!(seeds.try_recv(), Ok(42));
+        assert_eq!(minecraft.stats.snapshot().seed, Some(42));
+    }
+
+    #[test]
+    fn seeded_intervals() {
+        // pinned, a seed must pick the same waits after dependency updates
+        let mut rng = ChaCha8Rng::seed_from_u64(42);
+        let intervals: Vec<_> = (0..4).map(|_| Phase::sword().interval(&mut rng).as_micros()).collect();
+        assert_eq!(intervals, [71_517, 78_673, 64_733, 70_062]);
+    }
+
+    #[test]
+    fn extreme_ratios() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        for ratio in [f64::NAN, f64::INFINITY, 1e308] {
+            for distribution in [Distribution::Uniform { ratio }, Distribution::Normal { ratio }] {
+                let phase = Phase { distribution, ..Phase::sword() };
+                let interval = phase.interval(&mut rng);
+                assert!((MIN_MICROS..=MAX_MICROS).contains(&(interval.as_micros() as u64)));
+            }
+        }
+        let nan = Phase { distribution: Distribution::Uniform { ratio: f64::NAN }, ..Phase::sword() };
+        assert_eq!(nan.interval(&mut rng), Duration::from_micros(66_666));
+        let settings = Settings { phases: vec![Phase::sword(), nan], ..Settings::default() };
+        assert_eq!(settings.validate(), Err(String::from("phases.1.distribution: ratio must be a finite number")));
+
+        let table = Phase { distribution: Distribution::Table { micros: vec![u64::MAX, u64::MAX - 2] }, ..Phase::sword() };
+        assert_eq!(table.nominal_micros(), u64::MAX - 1);
+    }
+
+    #[test]
+    fn phase_defaults() {
+        let phase: Phase = serde_json::from_str(r#"{"slot": "Num1", "button": "Left", "count": 7, "sleep_micros": 66666}"#).unwrap();
+        assert_eq!(phase.distribution, Distribution::Uniform { ratio: 0.2 });
+        assert_eq!((phase.min_micros, phase.max_micros), (MIN_MICROS, MAX_MICROS));
+    }
+}
@@ -0,0 +1,78 @@
+use std::{
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    thread,
+    time::{Duration, Instant},
+};
+
+
+
+/// Profile waiting to be written and when.
+type Pending = Option<(String, Instant)>;
+
+
+/// Writes profiles from a background thread, merging saves requested within
+/// `delay` of each other into a single write.
+pub struct Saver {
+    delay: Duration,
+    pending: Arc<(Mutex<Pending>, Condvar)>,
+    /// Held while writing, so profiles are never written concurrently.
+    writing: Arc<Mutex<()>>,
+}
+
+impl Saver {
+    /// Spawns the thread calling `write` with the name of the profile to save.
+    pub fn new(delay: Duration, write: impl Fn(&str) + Send + 'static) -> Self {
+        let saver = Self {
+            delay,
+            pending: Arc::new((Mutex::new(None), Condvar::new())),
+            writing: Arc::new(Mutex::new(())),
+        };
+
+        let pending = Arc::clone(&saver.pending);
+        let writing = Arc::clone(&saver.writing);
+        thread::spawn(move || {
+            let (lock, cvar) = &*pending;
+            loop {
+                let mut next = cvar.wait_while(lock.lock().unwrap(), |next| next.is_none()).unwrap();
+                // each request pushes the write back
+                while let Some(due) = next.as_ref().map(|(_, due)| *due) {
+                    let now = Instant::now();
+                    if due <= now { break }
+                    next = cvar.wait_timeout(next, due - now).unwrap().0;
+                }
+                drop(next);
+
+                let _writing = writing.lock().unwrap();
+                // may have been taken by `pause` meanwhile
+                let next = lock.lock().unwrap().take();
+                if let Some((profile, _)) = next { write(&profile) }
+            }
+        });
+        saver
+    }
+
+    /// Writes `profile` once no save is requested for `delay`.
+    pub fn schedule(&self, profile: String) {
+        let (lock, cvar) = &*self.pending;
+        *lock.lock().unwrap() = Some((profile, Instant::now() + self.delay));
+        cvar.notify_one();
+    }
+
+    /// Cancels the pending write and returns its profile, so it can be
+    /// written right away. No background write starts until the guard drops.
+    pub fn pause(&self) -> (MutexGuard<'_, ()>, Option<String>) {
+        let writing = self.writing.lock().unwrap();
+        let pending = self.pending.0.lock().unwrap().take();
+        (writing, pending.map(|(profile, _)| profile))
+    }
+
+    /// Blocks background writes until the guard drops.
+    pub fn hold(&self) -> MutexGuard<'_, ()> {
+        self.writing.lock().unwrap()
+    }
+
+    /// Whether a save is waiting to be written.
+    pub fn is_pending(&self) -> bool {
+        self.pending.0.lock().unwrap().is_some()
+    }
+}
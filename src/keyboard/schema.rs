@@ -0,0 +1,139 @@
+use serde_json::{json, Map, Value};
+
+use super::run;
+
+
+
+/// Version of the settings and keybindings files written by this build.
+///
+/// Bump it whenever `run::Settings` or `minecraft::KeyBindings` change in a
+/// way older files no longer deserialize into, and add the step upgrading
+/// the previous version to `migrate_settings` or `migrate_keybindings`.
+///
+/// - 0: single sword and fishing rod phase, `[key, slot]` custom pairs,
+///   phase slots stored in the keybindings.
+/// - 1: phase list, return slot, chords, custom actions and profile hotkeys.
+/// - 2: timing distribution and interval bounds per phase, replacing
+///   `random_ratio`.
+pub const VERSION: u64 = 2;
+
+pub const VERSION_FIELD: &str = "version";
+
+
+/// Adds the current schema version to a serialized file.
+pub fn stamp(value: &mut Value) {
+    if let Value::Object(map) = value {
+        map.insert(String::from(VERSION_FIELD), json!(VERSION));
+    }
+}
+
+/// Version a file was written with. Files written before the version field
+/// existed are told apart by their shape.
+fn version(value: &Value, is_legacy: impl Fn(&Map<String, Value>) -> bool) -> u64 {
+    match value {
+        Value::Object(map) => match map.get(VERSION_FIELD).and_then(Value::as_u64) {
+            Some(version) => version,
+            None if is_legacy(map) => 0,
+            None => 1,
+        },
+        _ => VERSION,
+    }
+}
+
+pub fn settings_version(settings: &Value) -> u64 {
+    version(settings, |map| map.get("sleep_micros").is_some_and(Value::is_array))
+}
+
+pub fn keybindings_version(keybindings: &Value) -> u64 {
+    version(keybindings, |map| map.contains_key("sword") || map.contains_key("fishing_rod"))
+}
+
+
+/// Upgrades both files of a profile to the current version, returning
+/// whether each of them changed.
+///
+/// Files are migrated together as version 0 kept the phase slots in the
+/// keybindings. Files newer than this build are left untouched.
+pub fn migrate_profile(settings: Option<&mut Value>, keybindings: Option<&mut Value>) -> [bool; 2] {
+    let slots = keybindings.as_deref().map(|keybindings| (
+        keybindings.get("sword").cloned(),
+        keybindings.get("fishing_rod").cloned(),
+    ));
+    [
+        settings.is_some_and(|settings| migrate_settings(settings, slots.unwrap_or((None, None)))),
+        keybindings.is_some_and(migrate_keybindings),
+    ]
+}
+
+fn migrate_settings(settings: &mut Value, (sword, fishing_rod): (Option<Value>, Option<Value>)) -> bool {
+    let from = settings_version(settings);
+    if from >= VERSION { return false }
+    let Value::Object(map) = settings else { return false };
+
+    if from < 1 {
+        let pair = |map: &Map<String, Value>, field: &str, i: usize| map
+            .get(field)
+            .and_then(|pair| pair.get(i))
+            .cloned()
+            .unwrap_or(Value::Null);
+        let random_ratio = map.get("random_ratio").cloned().unwrap_or(json!(0.2));
+        let phases = json!([
+            {
+                "slot": sword.unwrap_or(json!("Num1")),
+                "button": "Left",
+                "count": pair(map, "count", 0),
+                "sleep_micros": pair(map, "sleep_micros", 0),
+                "random_ratio": random_ratio,
+            },
+            {
+                "slot": fishing_rod.unwrap_or(json!("Num2")),
+                "button": "Right",
+                "count": pair(map, "count", 1),
+                "sleep_micros": pair(map, "sleep_micros", 1),
+                "random_ratio": random_ratio,
+            },
+        ]);
+        map.remove("count");
+        map.remove("sleep_micros");
+        map.remove("random_ratio");
+        map.insert(String::from("phases"), phases);
+    }
+
+    if from < 2 {
+        if let Some(Value::Array(phases)) = map.get_mut("phases") {
+            for phase in phases.iter_mut().filter_map(Value::as_object_mut) {
+                let ratio = phase.remove("random_ratio").and_then(|ratio| ratio.as_f64()).unwrap_or(0.2);
+                // the ratio keeps the timing, the bounds are left wide so the
+                // interval can still be changed
+                phase.insert(String::from("distribution"), json!({ "Uniform": { "ratio": ratio } }));
+                phase.insert(String::from("min_micros"), json!(run::MIN_MICROS));
+                phase.insert(String::from("max_micros"), json!(run::MAX_MICROS));
+            }
+        }
+    }
+
+    stamp(settings);
+    true
+}
+
+fn migrate_keybindings(keybindings: &mut Value) -> bool {
+    let from = keybindings_version(keybindings);
+    if from >= VERSION { return false }
+    let Value::Object(map) = keybindings else { return false };
+
+    if from < 1 {
+        // the slots moved into the settings phases
+        map.remove("sword");
+        map.remove("fishing_rod");
+        if let Some(Value::Array(custom)) = map.get_mut("custom") {
+            for custom in custom.iter_mut() {
+                if let Some([hotkey, slot]) = custom.as_array().map(Vec::as_slice) {
+                    *custom = json!({ "hotkey": hotkey, "slot": slot, "action": "Use" });
+                }
+            }
+        }
+    }
+
+    stamp(keybindings);
+    true
+}
@@ -0,0 +1,267 @@
+use std::{
+    io::{Read, Write},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+
+use super::{
+    run, minecraft, profile, schema,
+    error::LoadError,
+    format::{self, Format},
+};
+
+
+
+/// Marks exported files, so other json files are not taken for profiles.
+const KIND: &str = "pvp-macro-profile";
+
+/// Starts profile codes, the digit is bumped if the encoding changes.
+pub const CODE_PREFIX: &str = "pvpm1:";
+
+/// Longest profile read from a code.
+const MAX_SIZE: u64 = 1 << 20;
+
+/// Extension of exported files.
+pub const EXTENSION: &str = "pvpm.json";
+
+/// Waits below this click faster than any player, which servers may flag.
+const SHORT_MICROS: u64 = 10_000;
+
+/// Custom actions longer than this block the macro for a noticeable time.
+const LONG_MILLIS: u64 = 3_000;
+
+
+/// Settings and keybindings of a profile bundled to be sent to someone.
+#[derive(Debug)]
+pub struct SharedProfile {
+    /// Name of the profile it was exported from.
+    pub name: String,
+    /// Version of the app it was exported from.
+    pub app_version: String,
+    /// Unix time of the export.
+    pub exported_at: u64,
+    pub settings: run::Settings,
+    pub keybindings: minecraft::KeyBindings,
+}
+
+/// Layout of exported files, the profile files are kept as values so older
+/// exports can be migrated.
+#[derive(Serialize, Deserialize)]
+struct Repr {
+    kind: String,
+    name: String,
+    app_version: String,
+    exported_at: u64,
+    settings: Value,
+    keybindings: Value,
+}
+
+impl SharedProfile {
+    pub fn new(name: &str, settings: run::Settings, keybindings: minecraft::KeyBindings) -> Self {
+        Self {
+            name: String::from(name),
+            app_version: String::from(crate::VERSION),
+            exported_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|time| time.as_secs())
+                .unwrap_or(0),
+            settings,
+            keybindings,
+        }
+    }
+
+    fn repr(&self) -> Result<Repr, String> {
+        let stamped = |value: Result<Value, serde_json::Error>| value
+            .map(|mut value| { schema::stamp(&mut value); value })
+            .map_err(|err| err.to_string());
+        Ok(Repr {
+            kind: String::from(KIND),
+            name: self.name.clone(),
+            app_version: self.app_version.clone(),
+            exported_at: self.exported_at,
+            settings: stamped(serde_json::to_value(&self.settings))?,
+            keybindings: stamped(serde_json::to_value(&self.keybindings))?,
+        })
+    }
+
+    /// Contents of an export file.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if the profile cannot be serialized.
+    pub fn to_file(&self) -> Result<String, String> {
+        serde_json::to_string_pretty(&self.repr()?).map(|text| text + "\n").map_err(|err| err.to_string())
+    }
+
+    /// Single line code to paste into chats, `CODE_PREFIX` followed by the
+    /// compressed profile in url safe base64.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if the profile cannot be serialized.
+    pub fn to_code(&self) -> Result<String, String> {
+        let json = serde_json::to_vec(&self.repr()?).map_err(|err| err.to_string())?;
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(&json).map_err(|err| err.to_string())?;
+        let compressed = encoder.finish().map_err(|err| err.to_string())?;
+        Ok(format!("{}{}", CODE_PREFIX, URL_SAFE_NO_PAD.encode(compressed)))
+    }
+
+    /// Reads a profile from the contents of an export file or from a code,
+    /// upgrading it if it was exported by an older version.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` describing the first problem if `text` is not a valid
+    /// profile.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim();
+        let json = match text.strip_prefix(CODE_PREFIX) {
+            Some(code) => decode(code)?,
+            None if text.starts_with('{') => String::from(text),
+            None => return Err(String::from("Not a profile file or code")),
+        };
+
+        let repr: Repr = Format::Json.parse(&json)
+            .and_then(format::from_migrated)
+            .map_err(|_| String::from("Not a profile file or code"))?;
+        if repr.kind != KIND { return Err(String::from("Not a profile file or code")) }
+        let (mut settings, mut keybindings) = (repr.settings, repr.keybindings);
+        schema::migrate_profile(Some(&mut settings), Some(&mut keybindings));
+        let settings = format::from_migrated(settings).map_err(|err| describe("Settings", err))?;
+        let keybindings = format::from_migrated(keybindings).map_err(|err| describe("Keybindings", err))?;
+
+        Ok(Self {
+            name: repr.name,
+            app_version: repr.app_version,
+            exported_at: repr.exported_at,
+            settings,
+            keybindings,
+        })
+    }
+
+    /// Things to check before importing, like hotkeys to profiles that do
+    /// not exist here.
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        for (binding, name) in self.keybindings.profile_select.iter() {
+            if !profile::exists(name) {
+                warnings.push(format!("{} switches to profile {:?}, which does not exist", binding, name));
+            }
+        }
+        if self.settings.phases.is_empty() {
+            warnings.push(String::from("The profile has no phase, the macro does nothing"));
+        }
+        for (i, phase) in self.settings.phases.iter().enumerate() {
+            let intervals = [
+                ("sleep_micros", phase.sleep_micros),
+                ("min_micros", phase.min_micros),
+                ("max_micros", phase.max_micros),
+            ];
+            for (field, micros) in intervals {
+                if micros < SHORT_MICROS {
+                    warnings.push(format!("Phase {} has {} of {}, which clicks very fast", i + 1, field, micros));
+                }
+            }
+        }
+        for custom in self.keybindings.custom.iter() {
+            // imported values may be anything, they must not overflow
+            let millis = custom.action.steps(custom.slot).iter()
+                .map(|step| step.hold_millis.saturating_add(step.delay_millis))
+                .fold(0, u64::saturating_add);
+            if millis > LONG_MILLIS {
+                warnings.push(format!("{} runs for {} ms, the macro waits for it", custom.hotkey, millis));
+            }
+        }
+        warnings
+    }
+}
+
+fn describe(part: &str, err: LoadError) -> String {
+    match err.field {
+        Some(field) => format!("{} ({}): {}", part, field, err.message),
+        None => format!("{}: {}", part, err.message),
+    }
+}
+
+fn decode(code: &str) -> Result<String, String> {
+    // codes pasted from chats may be wrapped
+    let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+    let compressed = URL_SAFE_NO_PAD.decode(code).map_err(|_| String::from("Profile code is damaged"))?;
+    let mut json = String::new();
+    // bounded, as a crafted code could inflate without end
+    DeflateDecoder::new(&compressed[..])
+        .take(MAX_SIZE)
+        .read_to_string(&mut json)
+        .map_err(|_| String::from("Profile code is damaged"))?;
+    Ok(json)
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rdev::{Button, Key};
+
+    fn fixture(version: u64) -> String {
+        let path = format!("{}/tests/fixtures/v{}/export.{}", env!("CARGO_MANIFEST_DIR"), version, EXTENSION);
+        std::fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn export_v1() {
+        let shared = SharedProfile::parse(&fixture(1)).unwrap();
+        assert_eq!((&shared.name[..], shared.exported_at), ("pvp", 1_760_000_001));
+        let phase = &shared.settings.phases[0];
+        assert_eq!(phase.distribution, run::Distribution::Uniform { ratio: 0.1 });
+        assert_eq!((phase.min_micros, phase.max_micros), (run::MIN_MICROS, run::MAX_MICROS));
+        assert_eq!(shared.keybindings.activation, minecraft::Activation::Timed { millis: 2000 });
+        assert_eq!(shared.keybindings.custom[1].action, minecraft::Action::Hold { millis: 1600 });
+    }
+
+    #[test]
+    fn export_v2() {
+        let shared = SharedProfile::parse(&fixture(2)).unwrap();
+        assert_eq!(shared.settings.phases[1].distribution, run::Distribution::Table { micros: vec![45_000, 50_000, 58_000] });
+        assert_eq!(shared.settings.seed, Some(42));
+        assert_eq!(shared.keybindings.custom[0].action, minecraft::Action::DoubleUse);
+    }
+
+    #[test]
+    fn code_round_trip() {
+        let shared = SharedProfile::parse(&fixture(1)).unwrap();
+        let parsed = SharedProfile::parse(&shared.to_code().unwrap()).unwrap();
+        assert_eq!(serde_json::to_value(&parsed.settings).unwrap(), serde_json::to_value(&shared.settings).unwrap());
+        assert_eq!(parsed.keybindings.custom[2].slot, Key::Num6);
+        assert!(SharedProfile::parse("pvpm1:AAAA").is_err());
+        assert!(SharedProfile::parse("{\"kind\": \"other\"}").is_err());
+    }
+
+    #[test]
+    fn warnings() {
+        let mut shared = SharedProfile::parse(&fixture(2)).unwrap();
+        assert!(shared.warnings().iter().all(|warning| !warning.contains("Phase") && !warning.contains(" ms")));
+
+        shared.settings.phases[0].min_micros = 0;
+        shared.keybindings.custom[1].action = minecraft::Action::Hold { millis: 5000 };
+        shared.keybindings.custom[2].action = minecraft::Action::Sequence(vec![
+            minecraft::Step { hold_millis: 2000, delay_millis: 1500, ..minecraft::Step::click(Button::Left) },
+        ]);
+        let warnings = shared.warnings();
+        assert!(warnings.iter().any(|warning| warning.starts_with("Phase 1 has min_micros of 0")));
+        assert!(warnings.iter().any(|warning| warning.ends_with("runs for 5000 ms, the macro waits for it")));
+        assert!(warnings.iter().any(|warning| warning.ends_with("runs for 3500 ms, the macro waits for it")));
+
+        shared.keybindings.custom[2].action = minecraft::Action::Sequence(vec![
+            minecraft::Step { hold_millis: u64::MAX, delay_millis: 1, ..minecraft::Step::click(Button::Left) },
+            minecraft::Step { hold_millis: u64::MAX, ..minecraft::Step::click(Button::Left) },
+        ]);
+        let expected = format!("runs for {} ms, the macro waits for it", u64::MAX);
+        assert!(shared.warnings().iter().any(|warning| warning.ends_with(&expected)));
+    }
+}
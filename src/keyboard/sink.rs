@@ -0,0 +1,77 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use rdev::{EventType, simulate};
+
+use super::clock::{Clock, RealClock};
+
+
+
+/// Destination of the input events emitted by [`super::minecraft::Minecraft`].
+pub trait InputSink: Send + 'static {
+    fn send(&mut self, event: &EventType);
+}
+
+
+/// Injects events into the running display server.
+#[derive(Default)]
+pub struct RdevSink;
+
+impl InputSink for RdevSink {
+    fn send(&mut self, event: &EventType) {
+        simulate(event).unwrap_or(());
+    }
+}
+
+
+/// Keeps every event in memory instead of injecting it.
+///
+/// Clones share the same buffer, so one clone can be handed to the worker
+/// while another is used to inspect what was sent.
+#[derive(Clone)]
+pub struct RecordingSink {
+    clock: Arc<dyn Clock>,
+    events: Arc<Mutex<Vec<(Duration, EventType)>>>,
+}
+
+impl Default for RecordingSink {
+    fn default() -> Self {
+        Self::with_clock(RealClock::new())
+    }
+}
+
+impl RecordingSink {
+    pub fn new() -> Self { Self::default() }
+
+    /// Timestamps events with the time of `clock`, which should be the one
+    /// of the worker sending them.
+    pub fn with_clock(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            clock,
+            events: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Returns recorded events with the time since the clock started, in
+    /// emission order.
+    pub fn events(&self) -> Vec<(Duration, EventType)> {
+        self.events.lock().unwrap().clone()
+    }
+
+    /// Returns recorded event types without timestamps.
+    pub fn event_types(&self) -> Vec<EventType> {
+        self.events.lock().unwrap().iter().map(|(_, event)| *event).collect()
+    }
+
+    pub fn clear(&self) {
+        self.events.lock().unwrap().clear();
+    }
+}
+
+impl InputSink for RecordingSink {
+    fn send(&mut self, event: &EventType) {
+        self.events.lock().unwrap().push((self.clock.now(), *event));
+    }
+}
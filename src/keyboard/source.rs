@@ -0,0 +1,63 @@
+use std::{
+    sync::mpsc,
+    time::SystemTime,
+};
+
+use rdev::{Event, EventType, listen};
+
+
+
+/// Origin of the input events handled by [`super::Listener`].
+pub trait EventSource {
+    /// Feeds events into `callback` until the source is exhausted.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if the source could not be listened.
+    fn listen<F: FnMut(Event) + 'static>(self, callback: F) -> Result<(), String>;
+}
+
+
+/// Listens global keyboard and mouse events of the display server.
+#[derive(Default)]
+pub struct RdevSource;
+
+impl EventSource for RdevSource {
+    fn listen<F: FnMut(Event) + 'static>(self, callback: F) -> Result<(), String> {
+        listen(callback).map_err(|err| format!("{:?}", err))
+    }
+}
+
+
+/// Yields events pushed through its paired sender.
+///
+/// Listening ends once every sender is dropped.
+pub struct ChannelSource {
+    rx: mpsc::Receiver<Event>,
+}
+
+impl ChannelSource {
+    pub fn new() -> (mpsc::Sender<Event>, Self) {
+        let (tx, rx) = mpsc::channel();
+        (tx, Self { rx })
+    }
+}
+
+impl EventSource for ChannelSource {
+    fn listen<F: FnMut(Event) + 'static>(self, mut callback: F) -> Result<(), String> {
+        while let Ok(event) = self.rx.recv() {
+            callback(event);
+        }
+        Ok(())
+    }
+}
+
+
+/// Wraps `event_type` in an [`Event`] stamped with the current time.
+pub fn synthetic(event_type: EventType) -> Event {
+    Event {
+        time: SystemTime::now(),
+        name: None,
+        event_type,
+    }
+}
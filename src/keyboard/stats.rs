@@ -0,0 +1,238 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use serde::Serialize;
+
+use super::clock::Clock;
+
+
+
+/// Intervals kept per phase for the mean and deviation.
+const INTERVALS: usize = 100;
+
+/// Wakeups kept for the lateness of the macro thread.
+const WAKEUPS: usize = 500;
+
+/// Span clicks per second are measured over.
+const CPS_WINDOW: Duration = Duration::from_secs(2);
+
+
+/// Counters of the macro and the worker sending its events, shared by both.
+pub struct Stats {
+    inner: Mutex<Inner>,
+    /// Kept when the counters are reset, as it belongs to the run.
+    seed: Mutex<Option<u64>>,
+    clock: Arc<dyn Clock>,
+}
+
+#[derive(Default)]
+struct Inner {
+    cycles: u64,
+    phases: Vec<PhaseCounter>,
+    custom_uses: u64,
+    skipped: u64,
+    dropped: u64,
+    events: u64,
+    /// When clicks sent within `CPS_WINDOW` went out.
+    recent_clicks: VecDeque<Duration>,
+    /// How long after their deadline the last clicks were sent.
+    lateness: VecDeque<Duration>,
+}
+
+#[derive(Default)]
+struct PhaseCounter {
+    requested: u64,
+    sent: u64,
+    configured_micros: u64,
+    /// Last click sent of the current run of the phase.
+    last: Option<Duration>,
+    intervals: VecDeque<Duration>,
+}
+
+
+/// Counters at one moment, see [`Stats::snapshot`].
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct Snapshot {
+    /// Times every phase ran through.
+    pub cycles: u64,
+    pub phases: Vec<PhaseSnapshot>,
+    pub custom_uses: u64,
+    /// Macro clicks left out as a custom use came in.
+    pub skipped: u64,
+    /// Macro clicks the worker threw away as they queued up during a custom
+    /// use.
+    pub dropped: u64,
+    /// Key and button events sent.
+    pub events: u64,
+    /// Clicks sent per second lately.
+    pub cps: f64,
+    /// Time clicks were sent after their deadline over the last ones, the
+    /// max bounds the jitter of the macro and the worker.
+    pub late_mean_micros: f64,
+    pub late_max_micros: f64,
+    /// Seed of the intervals of the last run, `None` if the generator was
+    /// injected or the macro has not started.
+    pub seed: Option<u64>,
+}
+
+/// Counters of the phase at the same index of `Settings::phases`.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct PhaseSnapshot {
+    /// Clicks the macro asked for.
+    pub requested: u64,
+    /// Clicks the worker sent.
+    pub sent: u64,
+    /// Wait the phase is configured to center on.
+    pub configured_micros: u64,
+    /// Measured time between sent clicks over the last ones.
+    pub mean_micros: f64,
+    pub stddev_micros: f64,
+}
+
+impl Stats {
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        Self { inner: Mutex::new(Inner::default()), seed: Mutex::new(None), clock }
+    }
+
+    /// The macro started a run picking intervals with `seed`.
+    pub fn seed(&self, seed: Option<u64>) {
+        *self.seed.lock().unwrap() = seed;
+    }
+
+    pub fn cycle(&self) {
+        self.inner.lock().unwrap().cycles += 1;
+    }
+
+    /// Marks the start of a run of phase `phase`, the wait before its first
+    /// click is not an interval.
+    pub fn start_phase(&self, phase: usize, configured_micros: u64) {
+        let mut inner = self.inner.lock().unwrap();
+        let counter = inner.phase(phase);
+        counter.configured_micros = configured_micros;
+        counter.last = None;
+    }
+
+    /// The macro asked for a click of phase `phase`.
+    pub fn request(&self, phase: usize) {
+        self.inner.lock().unwrap().phase(phase).requested += 1;
+    }
+
+    /// The worker sent a click, of phase `phase` if it came from the macro.
+    pub fn sent(&self, phase: Option<usize>) {
+        let now = self.clock.now();
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(phase) = phase {
+            let counter = inner.phase(phase);
+            counter.sent += 1;
+            if let Some(last) = counter.last.replace(now) {
+                if counter.intervals.len() == INTERVALS { counter.intervals.pop_front(); }
+                counter.intervals.push_back(now - last);
+            }
+        }
+        inner.recent_clicks.push_back(now);
+        inner.forget_clicks(now);
+    }
+
+    /// The worker sent a click `lateness` after its deadline.
+    pub fn late(&self, lateness: Duration) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.lateness.len() == WAKEUPS { inner.lateness.pop_front(); }
+        inner.lateness.push_back(lateness);
+    }
+
+    pub fn events(&self, count: usize) {
+        self.inner.lock().unwrap().events += count as u64;
+    }
+
+    /// A custom use started, the pause it makes in the phases is not an
+    /// interval.
+    pub fn custom_use(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.custom_uses += 1;
+        for counter in inner.phases.iter_mut() { counter.last = None }
+    }
+
+    pub fn skip(&self) {
+        self.inner.lock().unwrap().skipped += 1;
+    }
+
+    pub fn drop_click(&self) {
+        self.inner.lock().unwrap().dropped += 1;
+    }
+
+    /// Counts everything from zero again.
+    pub fn reset(&self) {
+        *self.inner.lock().unwrap() = Inner::default();
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        let mut inner = self.inner.lock().unwrap();
+        inner.forget_clicks(self.clock.now());
+        let lateness = inner.lateness.iter().map(micros);
+        let late_max = lateness.clone().fold(0.0, f64::max);
+        let late_mean = mean_stddev(lateness).0;
+        Snapshot {
+            cycles: inner.cycles,
+            phases: inner.phases.iter().map(PhaseCounter::snapshot).collect(),
+            custom_uses: inner.custom_uses,
+            skipped: inner.skipped,
+            dropped: inner.dropped,
+            events: inner.events,
+            cps: inner.cps(),
+            late_mean_micros: late_mean,
+            late_max_micros: late_max,
+            seed: *self.seed.lock().unwrap(),
+        }
+    }
+}
+
+impl Inner {
+    fn phase(&mut self, phase: usize) -> &mut PhaseCounter {
+        if self.phases.len() <= phase { self.phases.resize_with(phase + 1, PhaseCounter::default) }
+        &mut self.phases[phase]
+    }
+
+    /// Clicks per second between the first and last click of the window,
+    /// so a macro started just now is not measured as slower.
+    fn cps(&self) -> f64 {
+        match (self.recent_clicks.front(), self.recent_clicks.back()) {
+            (Some(first), Some(last)) if last > first => {
+                (self.recent_clicks.len() - 1) as f64 / (*last - *first).as_secs_f64()
+            },
+            _ => 0.0,
+        }
+    }
+
+    fn forget_clicks(&mut self, now: Duration) {
+        while self.recent_clicks.front().is_some_and(|click| now - *click > CPS_WINDOW) {
+            self.recent_clicks.pop_front();
+        }
+    }
+}
+
+impl PhaseCounter {
+    fn snapshot(&self) -> PhaseSnapshot {
+        let (mean, stddev) = mean_stddev(self.intervals.iter().map(micros));
+        PhaseSnapshot {
+            requested: self.requested,
+            sent: self.sent,
+            configured_micros: self.configured_micros,
+            mean_micros: mean,
+            stddev_micros: stddev,
+        }
+    }
+}
+
+fn micros(duration: &Duration) -> f64 { duration.as_secs_f64() * 1e6 }
+
+/// Mean and standard deviation of `values`, zero if there is none.
+fn mean_stddev(values: impl Iterator<Item = f64> + Clone) -> (f64, f64) {
+    let n = values.clone().count();
+    if n == 0 { return (0.0, 0.0) }
+    let mean = values.clone().sum::<f64>() / n as f64;
+    let variance = values.map(|value| (value - mean).powi(2)).sum::<f64>() / n as f64;
+    (mean, variance.sqrt())
+}
@@ -1,14 +1,108 @@
+#[cfg(feature = "gui")]
 mod assets;
+#[cfg(feature = "gui")]
 pub use assets::ASSETS_DIR;
 
+#[cfg(feature = "gui")]
 pub mod ui;
 pub mod keyboard;
+pub mod cli;
 
 pub static VERSION: &str = env!("CARGO_PKG_VERSION");
 pub static NAME: &str = env!("CARGO_PKG_NAME");
 
 use dirs;
-use std::path;
-pub fn data_dir() -> path::PathBuf { 
-    dirs::config_dir().unwrap().join("pvp-macro")
+use std::{
+    env, fs,
+    path::PathBuf,
+    sync::OnceLock,
+};
+
+/// Environment variable overriding the data directory.
+pub const HOME_VAR: &str = "PVP_MACRO_HOME";
+
+/// Folder next to the executable that makes the app portable, its files
+/// are kept there instead of the user's config directory.
+pub const PORTABLE_DIR: &str = "pvp-macro-data";
+
+static DATA_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Picks the data directory, creates it and keeps it for `data_dir`. The
+/// first of these is used: `flag`, `PVP_MACRO_HOME`, a `pvp-macro-data`
+/// folder next to the executable, the platform config directory.
+///
+/// Has no effect once the directory is picked.
+///
+/// # Errors
+///
+/// Returns `Err` if there is no config directory to fall back to or the
+/// directory cannot be created.
+pub fn init_data_dir(flag: Option<PathBuf>) -> Result<PathBuf, String> {
+    if let Some(dir) = DATA_DIR.get() { return Ok(dir.clone()) }
+
+    let portable = || env::current_exe().ok()
+        .and_then(|exe| Some(exe.parent()?.join(PORTABLE_DIR)))
+        .filter(|dir| dir.is_dir());
+    let dir = flag
+        .or_else(|| env::var_os(HOME_VAR).filter(|home| !home.is_empty()).map(PathBuf::from))
+        .or_else(portable)
+        .or_else(|| dirs::config_dir().map(|config| config.join("pvp-macro")))
+        .ok_or_else(|| format!("No config directory found, set {} to pick one", HOME_VAR))?;
+    fs::create_dir_all(&dir).map_err(|err| format!("Cannot create {}: {}", dir.display(), err))?;
+
+    Ok(DATA_DIR.get_or_init(|| dir).clone())
+}
+
+/// Directory of profiles and other files, as picked by `init_data_dir`.
+///
+/// # Errors
+///
+/// Returns `Err` if `init_data_dir` was not called and fails now.
+#[cfg(not(test))]
+pub fn data_dir() -> Result<PathBuf, String> {
+    match DATA_DIR.get() {
+        Some(dir) => Ok(dir.clone()),
+        None => init_data_dir(None),
+    }
+}
+
+/// Tests only see the directory of the current `TestDataDir`, never the
+/// directory of the user.
+#[cfg(test)]
+pub fn data_dir() -> Result<PathBuf, String> {
+    TEST_DATA_DIR.lock().unwrap().clone().ok_or_else(|| String::from("No data directory in this test"))
+}
+
+#[cfg(test)]
+static TEST_DATA_DIR: std::sync::Mutex<Option<PathBuf>> = std::sync::Mutex::new(None);
+
+/// Taken by each `TestDataDir`, so tests using one run one at a time.
+#[cfg(test)]
+static TEST_DATA_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+/// Temporary data directory of a test, removed on drop.
+#[cfg(test)]
+pub(crate) struct TestDataDir {
+    dir: PathBuf,
+    _lock: std::sync::MutexGuard<'static, ()>,
+}
+
+#[cfg(test)]
+impl TestDataDir {
+    pub fn new(name: &str) -> Self {
+        // a failed test must not block the others
+        let lock = TEST_DATA_LOCK.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+        let dir = env::temp_dir().join(format!("pvp-macro-test-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&dir).unwrap();
+        *TEST_DATA_DIR.lock().unwrap() = Some(dir.clone());
+        Self { dir, _lock: lock }
+    }
+}
+
+#[cfg(test)]
+impl Drop for TestDataDir {
+    fn drop(&mut self) {
+        *TEST_DATA_DIR.lock().unwrap() = None;
+        fs::remove_dir_all(&self.dir).unwrap_or(());
+    }
 }
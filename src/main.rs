@@ -1,30 +1,95 @@
-#![windows_subsystem = "windows"]
+#![cfg_attr(feature = "gui", windows_subsystem = "windows")]
 
+#[cfg(feature = "gui")]
 use pvp_macro::{ 
     ui::UI,
     keyboard
 };
+use pvp_macro::cli::{self, Args, Command};
+use std::{env, process};
+#[cfg(feature = "gui")]
 use std::{
     thread,
     time::Duration,
     sync::Arc,
-    fs
 };
 
 fn main() {
-    fs::create_dir(pvp_macro::data_dir()).unwrap_or(());
+    let Args { data_dir, command } = Args::parse(env::args().skip(1)).unwrap_or_else(|err| {
+        #[cfg(all(windows, feature = "gui"))]
+        attach_console();
+        eprintln!("{}\n\n{}", err, cli::USAGE);
+        process::exit(2);
+    });
+    #[cfg(all(windows, feature = "gui"))]
+    if command != Command::Gui { attach_console() }
+    if command != Command::Help {
+        if let Err(err) = pvp_macro::init_data_dir(data_dir) {
+            eprintln!("Error: {}", err);
+            // there is no console to print to on windows
+            #[cfg(feature = "gui")]
+            if command == Command::Gui { fltk::dialog::alert_default(&err) }
+            process::exit(1);
+        }
+    }
+    match command {
+        #[cfg(feature = "gui")]
+        Command::Gui => run_gui(),
+        #[cfg(not(feature = "gui"))]
+        Command::Gui => {
+            eprintln!("Built without the window, run with --headless\n\n{}", cli::USAGE);
+            process::exit(2);
+        },
+        Command::Headless { profile } => {
+            if let Err(err) = cli::run_headless(profile.as_deref()) {
+                eprintln!("Error: {}", err);
+                process::exit(1);
+            }
+        },
+        #[cfg(unix)]
+        Command::Ctl(request) => {
+            if let Err(err) = cli::run_ctl(&request) {
+                eprintln!("Error: {}", err);
+                process::exit(1);
+            }
+        },
+        Command::Help => println!("{}", cli::USAGE),
+    }
+}
+
+/// Prints into the console the app was started from, which windows does
+/// not give to apps with a window.
+#[cfg(all(windows, feature = "gui"))]
+fn attach_console() {
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn AttachConsole(process_id: u32) -> i32;
+    }
+    const ATTACH_PARENT_PROCESS: u32 = u32::MAX;
+    // started from explorer there is no console, nothing to attach to
+    unsafe { AttachConsole(ATTACH_PARENT_PROCESS) };
+}
 
+#[cfg(feature = "gui")]
+fn run_gui() {
     let listener = keyboard::Listener::new();
     //listener.load_keybindings(data_dir().join("keybindings.json")).unwrap();
     listener.load_settings();
+    listener.watch();
+    #[cfg(unix)]
+    let _control = keyboard::control::serve(&listener).ok();
 
     let listener2 = Arc::clone(&listener);
     thread::spawn(move || {
         thread::sleep(Duration::from_secs(1));
-        listener2.listen();
+        if let Err(err) = listener2.listen() {
+            // dialogs are shown from the main thread
+            fltk::app::awake_callback(move || fltk::dialog::alert_default(&format!("Klavye dinlenemiyor: {}", err)));
+        }
     });
 
     let ui = UI::new(Arc::clone(&listener));
     ui.init();
     ui.run();
+    listener.flush_settings();
 }
@@ -1,9 +1,17 @@
 use fltk::{prelude::*, *};
-use rdev::Key;
+use rdev::{Key, Button};
 
-use crate::keyboard::{Listener, run};
+use crate::keyboard::{
+    Listener, Binding, Format, SharedProfile, Snapshot, run,
+    share::EXTENSION,
+    minecraft::{Activation, Action},
+};
 
-use std::sync::{Arc, Mutex};
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    sync::{Arc, Mutex},
+};
 
 use super::{ 
     theme::{self, format_button}, Theme,
@@ -13,11 +21,21 @@ use webbrowser;
 
 pub type MenuFrame<'a> = &'a mut group::Flex;
 
+/// Hotbar slot keys, in order.
+const SLOTS: [Key; 9] = [
+    Key::Num1, Key::Num2, Key::Num3, Key::Num4, Key::Num5,
+    Key::Num6, Key::Num7, Key::Num8, Key::Num9,
+];
+
 
 pub fn settings(frame: MenuFrame, listener: Arc<Listener>) {
-    enum In {
-        F (f64, Box<dyn FnMut(&mut run::Settings, f64) -> ()>),
-        I (u64, Box<dyn FnMut(&mut run::Settings, u64) -> ()>),
+    fn field_label(frame: MenuFrame, text: String) {
+        let mut text_label = frame::Frame::default();
+        frame.fixed(&text_label, 16);
+        text_label.draw(move |b| {
+            draw::set_draw_color(Theme::COLOR);
+            draw::draw_text(&text, b.x(), b.y() + b.h())
+        });
     }
 
     fn input_num_field(frame: MenuFrame, 
@@ -27,12 +45,7 @@ pub fn settings(frame: MenuFrame, listener: Arc<Listener>) {
     ) {
         frame.begin();
 
-        let mut text_label = frame::Frame::default();
-        frame.fixed(&text_label, 16);
-        text_label.draw(move |b| {
-            draw::set_draw_color(Theme::COLOR);
-            draw::draw_text(&text, b.x(), b.y() + b.h())
-        });
+        field_label(frame, text);
         frame.fixed(input, 24);
         theme::format_input(input);
 
@@ -43,95 +56,308 @@ pub fn settings(frame: MenuFrame, listener: Arc<Listener>) {
         frame.end();
     }
 
+    /// Hands the text of `input` to `edit` on every key press and saves the
+    /// settings, marking the text if `edit` could not use it.
+    fn on_edit(
+        input: &mut (impl InputExt + WidgetBase),
+        listener: &Arc<Listener>,
+        mut edit: impl FnMut(&mut run::Settings, &str) -> bool + 'static,
+    ) {
+        let listener = Arc::clone(listener);
+        input.handle(move |input, event| {
+            if !matches!(event, enums::Event::KeyDown) { return false }
+            let valid = edit(&mut listener.service.settings.lock().unwrap(), &input.value());
+            input.set_text_color(if valid { Theme::COLOR } else { Theme::WARN });
+            listener.save_settings();
+            true
+        });
+    }
+
+    fn millis(micros: u64) -> String {
+        (((micros as f64) / 10.0).round() / 100.0).to_string()
+    }
+
+    fn percent(ratio: f64) -> String {
+        ((ratio * 10000.0).round() / 100.0).to_string()
+    }
+
+    fn fill_picker(picker: &mut menu::Choice, len: usize, selected: usize) {
+        picker.clear();
+        for i in 0..len {
+            picker.add_choice(&format!("{}. aşama", i + 1)[..]);
+        }
+        picker.set_value(selected as i32);
+    }
+
 
     let listener2 = Arc::clone(&listener);
-    let load_settings = move |frame: &Arc<Mutex<group::Flex>>| {
+    let load_settings = Rc::new(move |frame: &Arc<Mutex<group::Flex>>, i: usize| {
         let listener = &listener2;
         let settings = listener.service.settings.lock().unwrap();
         let mut frame = frame.lock().unwrap();
-        for i in (0..frame.children()).rev() {
-            app::delete_widget(frame.child(i).unwrap());
+        for j in (0..frame.children()).rev() {
+            app::delete_widget(frame.child(j).unwrap());
         }
 
-        let settings_data = [
-            (
-                "Kılıç CPS", 
-                In::F((1.0e8 / (settings.sleep_micros[0] as f64)).round() / 100.0, Box::new(|s, v| {
-                    s.sleep_micros[0] = (1.0e6 / v) as u64
-                }))
-            ),
-            (
-                "Kılıç vurma sayısı",
-                In::I(settings.count[0], Box::new(|s, v| {
-                    s.count[0] = v;
-                }))
-            ),
-            (
-                "Olta atma sayısı",
-                In::I(settings.count[1], Box::new(|s, v| { 
-                    s.count[1] = v;
-                }))
-            ),
-            (
-                "Olta başına atma süresi",
-                In::F(((settings.sleep_micros[1] as f64) / 1.0e4).round() / 100.0, Box::new(|s, v| {
-                    s.sleep_micros[1] = (v * 1.0e6) as u64
-                }))
-            ),
-            (
-                "Rastgelelik yüzdesi",
-                In::F((settings.random_ratio as f64 * 10000.0).round() / 100.0, Box::new(|s, v| {
-                    s.random_ratio = v / 100.0
-                }))
-            ),
-            ];
-
-        for (text, input_type) in settings_data.into_iter() {
-            let settings = Arc::clone(&listener.service.settings);
-            let listener = Arc::clone(&listener);
-            match input_type {
-                In::F(default, mut cb) => {
-                    let mut input = input::FloatInput::default();
-                    input_num_field(&mut frame, String::from(text), &mut input, default.to_string());
-                    input.handle(move |input, event| {
-                        if !matches!(event, enums::Event::KeyDown) { return false }
-                        if let Ok(value) = input.value().parse::<f64>() {
-                            let mut settings = settings.lock().unwrap();
-                            cb(&mut settings, value);
-                            input.set_text_color(Theme::COLOR);
-                        } else {
-                            input.set_text_color(Theme::WARN);
-                        }
-                        listener.save_settings();
-                        true
-                    });
+        let phase = match settings.phases.get(i) {
+            Some(phase) => phase,
+            None => return app::redraw(),
+        };
+        let button = phase.button;
+
+        let count = phase.count;
+        let micros = [phase.sleep_micros, phase.min_micros, phase.max_micros];
+        let distribution = phase.distribution.clone();
+        let return_slot = settings.return_slot;
+        let return_delay = settings.return_delay_millis;
+        drop(settings);
+
+        // interval and its bounds
+        frame.begin();
+        field_label(&mut frame, String::from("Aralık, en kısa, en uzun (ms)"));
+        let mut row = group::Flex::default();
+        row.set_type(group::FlexType::Row);
+        frame.fixed(&row, 24);
+        for (j, micros) in micros.into_iter().enumerate() {
+            let mut input = input::FloatInput::default();
+            theme::format_input(&mut input);
+            input.set_value(&millis(micros)[..]);
+            on_edit(&mut input, listener, move |s, text| {
+                let Ok(value) = text.parse::<f64>() else { return false };
+                let Some(phase) = s.phases.get_mut(i) else { return true };
+                let field = match j {
+                    0 => &mut phase.sleep_micros,
+                    1 => &mut phase.min_micros,
+                    _ => &mut phase.max_micros,
+                };
+                *field = (value * 1.0e3) as u64;
+                // an interval outside the bounds is clamped to them
+                (phase.min_micros..=phase.max_micros).contains(&phase.sleep_micros)
+            });
+        }
+        row.end();
+        frame.end();
+
+        let mut input = input::IntInput::default();
+        input_num_field(&mut frame, String::from("Tekrar sayısı"), &mut input, count.to_string());
+        on_edit(&mut input, listener, move |s, text| {
+            let Ok(value) = text.parse::<u64>() else { return false };
+            if let Some(phase) = s.phases.get_mut(i) { phase.count = value }
+            true
+        });
+
+        frame.begin();
+        field_label(&mut frame, String::from("Fare tuşu"));
+        let mut choice = menu::Choice::default();
+        frame.fixed(&choice, 24);
+        frame.end();
+        choice.set_frame(enums::FrameType::FlatBox);
+        choice.set_color(Theme::BG_2);
+        choice.add_choice("Sol tık|Sağ tık");
+        choice.set_value(if button == Button::Left { 0 } else { 1 });
+        let listener2 = Arc::clone(listener);
+        choice.set_callback(move |choice| {
+            if let Some(phase) = listener2.service.settings.lock().unwrap().phases.get_mut(i) {
+                phase.button = if choice.value() == 0 { Button::Left } else { Button::Right };
+            }
+            listener2.save_settings();
+        });
+
+        frame.begin();
+        field_label(&mut frame, String::from("Dağılım"));
+        let mut row = group::Flex::default();
+        row.set_type(group::FlexType::Row);
+        frame.fixed(&row, 24);
+        let mut choice = menu::Choice::default();
+        choice.set_frame(enums::FrameType::FlatBox);
+        choice.set_color(Theme::BG_2);
+        choice.add_choice("Sabit|Düzgün|Normal|Tablo");
+        let mut input = input::Input::default();
+        theme::format_input(&mut input);
+        row.end();
+        frame.end();
+
+        let show = move |input: &mut input::Input, distribution: &run::Distribution| {
+            let (text, tooltip) = match distribution {
+                run::Distribution::Fixed => (String::new(), ""),
+                run::Distribution::Uniform { ratio } => (percent(*ratio), "Rastgelelik yüzdesi, aralığın ± kaçı"),
+                run::Distribution::Normal { ratio } => (percent(*ratio), "Sapma yüzdesi, aralığın kaçı"),
+                run::Distribution::Table { micros } => (
+                    micros.iter().map(|micros| millis(*micros)).collect::<Vec<_>>().join(", "),
+                    "Rastgele seçilen aralıklar (ms), virgülle ayrılmış",
+                ),
+            };
+            input.set_value(&text[..]);
+            input.set_tooltip(tooltip);
+            if matches!(distribution, run::Distribution::Fixed) { input.deactivate() } else { input.activate() }
+        };
+        choice.set_value(match distribution {
+            run::Distribution::Fixed => 0,
+            run::Distribution::Uniform { .. } => 1,
+            run::Distribution::Normal { .. } => 2,
+            run::Distribution::Table { .. } => 3,
+        });
+        show(&mut input, &distribution);
+
+        let listener2 = Arc::clone(listener);
+        let mut input2 = input.clone();
+        choice.set_callback(move |choice| {
+            let mut settings = listener2.service.settings.lock().unwrap();
+            let Some(phase) = settings.phases.get_mut(i) else { return };
+            // the spread is kept when switching between the random ones
+            let ratio = match phase.distribution {
+                run::Distribution::Uniform { ratio } | run::Distribution::Normal { ratio } => ratio,
+                _ => 0.2,
+            };
+            phase.distribution = match choice.value() {
+                0 => run::Distribution::Fixed,
+                1 => run::Distribution::Uniform { ratio },
+                2 => run::Distribution::Normal { ratio },
+                _ => run::Distribution::Table { micros: vec![phase.sleep_micros] },
+            };
+            show(&mut input2, &phase.distribution);
+            drop(settings);
+            listener2.save_settings();
+        });
+        on_edit(&mut input, listener, move |s, text| {
+            let Some(phase) = s.phases.get_mut(i) else { return true };
+            match &mut phase.distribution {
+                run::Distribution::Fixed => true,
+                run::Distribution::Uniform { ratio } | run::Distribution::Normal { ratio } => {
+                    match text.parse::<f64>() {
+                        Ok(value) if value.is_finite() && value >= 0.0 => { *ratio = value / 100.0; true },
+                        _ => false,
+                    }
                 },
-                In::I(default, mut cb) => {
-                    let mut input = input::IntInput::default();
-                    input_num_field(&mut frame, String::from(text), &mut input, default.to_string());
-                    input.handle(move |input, event| {
-                        if !matches!(event, enums::Event::KeyDown) { return false }
-                        if let Ok(value) = input.value().parse::<u64>() {
-                            let mut settings = settings.lock().unwrap();
-                            cb(&mut settings, value);
-                            input.set_text_color(Theme::COLOR);
-                        } else {
-                            input.set_text_color(Theme::WARN);
-                        }
-                        listener.save_settings();
-                        true
-                    });
+                run::Distribution::Table { micros } => {
+                    let table: Result<Vec<_>, _> = text
+                        .split(',')
+                        .map(|value| value.trim().parse::<f64>().map(|value| (value * 1.0e3) as u64))
+                        .collect();
+                    match table {
+                        Ok(table) => { *micros = table; true },
+                        Err(_) => false,
+                    }
                 },
             }
+        });
+
+        // shared by all phases
+        frame.begin();
+        field_label(&mut frame, String::from("Özel kullanımdan sonra"));
+        let mut choice = menu::Choice::default();
+        frame.fixed(&choice, 24);
+        frame.end();
+        choice.set_frame(enums::FrameType::FlatBox);
+        choice.set_color(Theme::BG_2);
+        choice.add_choice("Slotta kal|Önceki slota dön");
+        for slot in 1..=SLOTS.len() {
+            choice.add_choice(&format!("{}. slota dön", slot)[..]);
         }
+        let value = match return_slot {
+            run::ReturnSlot::Stay => 0,
+            run::ReturnSlot::Previous => 1,
+            run::ReturnSlot::Slot(key) => match SLOTS.iter().position(|slot| *slot == key) {
+                Some(slot) => slot as i32 + 2,
+                None => choice.add_choice(&format!("{:?} slotuna dön", key)[..]),
+            },
+        };
+        choice.set_value(value);
+        let listener2 = Arc::clone(listener);
+        choice.set_callback(move |choice| {
+            let mut settings = listener2.service.settings.lock().unwrap();
+            settings.return_slot = match choice.value() {
+                0 => run::ReturnSlot::Stay,
+                1 => run::ReturnSlot::Previous,
+                slot => match SLOTS.get(slot as usize - 2) {
+                    Some(key) => run::ReturnSlot::Slot(*key),
+                    None => settings.return_slot,
+                },
+            };
+            drop(settings);
+            listener2.save_settings();
+        });
+
+        let mut input = input::IntInput::default();
+        input_num_field(&mut frame, String::from("Dönüş gecikmesi (ms)"), &mut input, return_delay.to_string());
+        let listener2 = Arc::clone(listener);
+        input.handle(move |input, event| {
+            if !matches!(event, enums::Event::KeyDown) { return false }
+            if let Ok(value) = input.value().parse::<u64>() {
+                listener2.service.settings.lock().unwrap().return_delay_millis = value;
+                input.set_text_color(Theme::COLOR);
+            } else {
+                input.set_text_color(Theme::WARN);
+            }
+            listener2.save_settings();
+            true
+        });
         app::redraw();
-    };
+    });
     frame.begin();
     frame.set_type(group::FlexType::Column);
 
+    // phase picker
+    let mut top = group::Flex::default();
+    let mut picker = menu::Choice::default();
+    picker.set_frame(enums::FrameType::FlatBox);
+    picker.set_color(Theme::BG_2);
+    let mut add = button::Button::default().with_label("+");
+    let mut remove = button::Button::default().with_label("-");
+    top.fixed(&add, 24);
+    top.fixed(&remove, 24);
+    top.end();
+    frame.fixed(&top, 24);
+    fill_picker(&mut picker, listener.service.settings.lock().unwrap().phases.len(), 0);
+
     let frame_mutex = Arc::new(Mutex::new(group::Flex::default()));
     frame_mutex.lock().unwrap().set_type(group::FlexType::Column);
-    load_settings(&frame_mutex);
+    load_settings(&frame_mutex, 0);
+
+    let frame2 = Arc::clone(&frame_mutex);
+    let load2 = Rc::clone(&load_settings);
+    picker.set_callback(move |picker| {
+        load2(&frame2, picker.value().max(0) as usize);
+    });
+
+    let frame2 = Arc::clone(&frame_mutex);
+    let load2 = Rc::clone(&load_settings);
+    let listener2 = Arc::clone(&listener);
+    let mut picker2 = picker.clone();
+    add.set_callback(move |_| {
+        let mut settings = listener2.service.settings.lock().unwrap();
+        let phase = settings.phases.last().cloned().unwrap_or_else(run::Phase::sword);
+        settings.phases.push(phase);
+        let len = settings.phases.len();
+        drop(settings);
+
+        fill_picker(&mut picker2, len, len - 1);
+        load2(&frame2, len - 1);
+        listener2.save_settings();
+    });
+
+    let frame2 = Arc::clone(&frame_mutex);
+    let load2 = Rc::clone(&load_settings);
+    let listener2 = Arc::clone(&listener);
+    let mut picker2 = picker.clone();
+    remove.set_callback(move |_| {
+        let mut settings = listener2.service.settings.lock().unwrap();
+        let selected = picker2.value().max(0) as usize;
+        if settings.phases.len() <= 1 || selected >= settings.phases.len() { return }
+        settings.phases.remove(selected);
+        let len = settings.phases.len();
+        drop(settings);
+
+        let selected = selected.min(len - 1);
+        fill_picker(&mut picker2, len, selected);
+        load2(&frame2, selected);
+        listener2.save_settings();
+    });
+
+    for btn in [&mut add, &mut remove] {
+        format_button(btn);
+        btn_cursor(btn);
+    }
 
     let _ = frame::Frame::default();
     let mut f = group::Flex::default();
@@ -139,9 +365,12 @@ pub fn settings(frame: MenuFrame, listener: Arc<Listener>) {
     let mut reset = button::Button::default().with_label("Sıfırla");
     let frame2 = Arc::clone(&frame_mutex);
     let listener2 = Arc::clone(&listener);
+    let mut picker2 = picker.clone();
     reset.set_callback(move |_| {
         listener2.service.reset_settings();
-        load_settings(&frame2);
+        let len = listener2.service.settings.lock().unwrap().phases.len();
+        fill_picker(&mut picker2, len, 0);
+        load_settings(&frame2, 0);
     });
     f.fixed(&reset, reset.measure_label().0 + 16);
     f.end();
@@ -193,84 +422,160 @@ pub fn info(frame: MenuFrame) {
 
 pub fn run(frame: MenuFrame, listener: Arc<Listener>) {
     frame.begin();
-    let _ = frame::Frame::default().with_label("PvP macro çalışıyor.");
+    frame.set_type(group::FlexType::Column);
+
+    let label = frame::Frame::default().with_label("PvP macro çalışıyor.");
+    frame.fixed(&label, 24);
+
+    let mut stats = frame::Frame::default()
+        .with_align(enums::Align::Left | enums::Align::Top | enums::Align::Inside);
+    stats.set_label_font(enums::Font::Courier);
+    stats.set_label_size(12);
+
+    let mut reset = button::Button::default().with_label("Sayaçları sıfırla");
+    frame.fixed(&reset, 24);
+    format_button(&mut reset);
+    btn_cursor(&mut reset);
+
     listener.start().unwrap_or(());
     frame.end();
+
+    let listener2 = Arc::clone(&listener);
+    reset.set_callback(move |_| listener2.reset_stats());
+
+    stats.set_label(&stats_text(&listener.stats()));
+    // stops once the menu is left and the label deleted
+    app::add_timeout3(0.25, move |handle| {
+        if stats.was_deleted() { return }
+        stats.set_label(&stats_text(&listener.stats()));
+        app::repeat_timeout3(0.25, handle);
+    });
 }
 
+fn stats_text(stats: &Snapshot) -> String {
+    let mut text = format!(
+        "Tık/sn: {:.1}\nTur: {}\nÖzel kullanım: {}\nAtlanan: {}  Düşen: {}\nOlay: {}\nGecikme: {:.2} ms, en çok {:.2} ms\n",
+        stats.cps, stats.cycles, stats.custom_uses, stats.skipped, stats.dropped, stats.events,
+        stats.late_mean_micros / 1000.0, stats.late_max_micros / 1000.0,
+    );
+    if let Some(seed) = stats.seed {
+        text += &format!("Tohum: {}\n", seed);
+    }
+    for (i, phase) in stats.phases.iter().enumerate() {
+        text += &format!(
+            "\nAşama {}: {}/{} tık\n  {:.1} ± {:.1} ms ({:.1})",
+            i + 1,
+            phase.sent,
+            phase.requested,
+            phase.mean_micros / 1000.0,
+            phase.stddev_micros / 1000.0,
+            phase.configured_micros as f64 / 1000.0,
+        );
+    }
+    text
+}
 
-pub fn keybindings(frame: MenuFrame, listener: Arc<Listener>) {
-    frame.begin();
-    frame.set_type(group::FlexType::Column);
 
+/// Request passed to a keybinding accessor, which answers with the label of
+/// the binding after handling it.
+enum Edit {
+    Get,
+    /// Captured binding, `None` if capturing was cancelled with a mouse click.
+    Set(Option<Binding>),
+}
 
-    let mut buttons: Vec<(group::Flex, button::Button, Arc<Mutex<Box<dyn FnMut(Option<Key>) -> Key>>>)> = Vec::new();
+impl Edit {
+    /// Captured key for hotbar slots, which cannot be chords or mouse buttons.
+    fn slot(&self) -> Option<Key> {
+        match self {
+            Edit::Set(Some(binding)) if binding.modifiers.is_empty() => binding.key(),
+            _ => None,
+        }
+    }
+}
 
-    let mut keybinding = |label: &str, keys: Vec<Box<dyn FnMut(Option<Key>) -> Key>>| -> group::Flex {
-        let mut flex = group::Flex::default();
+type KeyEdit = Box<dyn FnMut(Edit) -> String>;
+type KeyButtons = Vec<(group::Flex, button::Button, Rc<RefCell<KeyEdit>>)>;
 
-        let frame = frame::Frame::default().with_label(label);
-        flex.fixed(&frame, frame.measure_label().0 + 8);
-        let _ = frame::Frame::default();
-        for mut key in keys {
-            let mut btn = button::Button::default();
+fn key_label(binding: Option<Binding>) -> String {
+    binding.map(|binding| binding.to_string()).unwrap_or(String::from("-"))
+}
 
-            let mut flex2 = flex.clone();
-            let mut btn2 = btn.clone();
-            let mut set_btn_label = move |label: String| {
-                btn2.set_label(&label[..]);
-                flex2.fixed(&btn2, btn2.measure_label().0 + 16);
-                app::redraw();
-            };
-            set_btn_label(format!("{:?}", key(None)));
-
-            let listener = Arc::clone(&listener);
-            let key = Arc::new(Mutex::new(key));
-            let key2 = Arc::clone(&key);
-            btn.set_callback(move |_| {
-                let mut key = key2.lock().unwrap();
-                key(listener.await_key());
-                set_btn_label(format!("{:?}", key(None)));
-                listener.save_settings();
-            });
+/// Row of buttons capturing a key for each accessor in `keys` when clicked.
+fn keybinding_row(listener: &Arc<Listener>, buttons: &mut KeyButtons, label: &str, keys: Vec<KeyEdit>) -> group::Flex {
+    let mut flex = group::Flex::default();
 
-            format_button(&mut btn);
-            btn_cursor(&mut btn);
-            buttons.push((flex.clone(), btn, key));
-        }
+    let frame = frame::Frame::default().with_label(label);
+    flex.fixed(&frame, frame.measure_label().0 + 8);
+    let _ = frame::Frame::default();
+    for mut key in keys {
+        let mut btn = button::Button::default();
+
+        let mut flex2 = flex.clone();
+        let mut btn2 = btn.clone();
+        let mut set_btn_label = move |label: String| {
+            btn2.set_label(&label[..]);
+            flex2.fixed(&btn2, btn2.measure_label().0 + 16);
+            app::redraw();
+        };
+        set_btn_label(key(Edit::Get));
+
+        let listener = Arc::clone(listener);
+        let key = Rc::new(RefCell::new(key));
+        let key2 = Rc::clone(&key);
+        btn.set_callback(move |_| {
+            let mut key = key2.borrow_mut();
+            set_btn_label(key(Edit::Set(listener.await_key())));
+            listener.save_settings();
+        });
+
+        format_button(&mut btn);
+        btn_cursor(&mut btn);
+        buttons.push((flex.clone(), btn, key));
+    }
 
-        flex.end();
-        flex
-    };
+    flex.end();
+    flex
+}
+
+fn refresh_key_labels(buttons: &mut KeyButtons) {
+    for (flex, btn, key) in buttons {
+        btn.set_label(&(*key.borrow_mut())(Edit::Get)[..]);
+        flex.fixed(btn, btn.measure_label().0 + 16);
+    }
+    app::redraw();
+}
+
+
+pub fn keybindings(frame: MenuFrame, listener: Arc<Listener>) {
+    frame.begin();
+    frame.set_type(group::FlexType::Column);
+
+
+    let mut buttons: KeyButtons = Vec::new();
 
     macro_rules! keybindings {
-        ($text: expr, [$ident: ident]) => {
+        ($text: expr, ?$ident: ident) => {
             {
-                let listener = Arc::clone(&listener);
-                let len = listener.minecraft.keybindings.lock().unwrap().$ident.len();
-                for x in 0..len {
-                    let mut vec = Vec::new();
-                    for a in 0..2 {
-                        let listener = Arc::clone(&listener);
-                        vec.push(Box::new(move |set| { 
-                            let mut keybindings = listener.minecraft.keybindings.lock().unwrap();
-                            if let Some(key) = set { keybindings.$ident[x][a] = key }
-                            keybindings.$ident[x][a]
-                        }) as Box<dyn FnMut(std::option::Option<rdev::Key>) -> rdev::Key>)
-                    }
-                    frame.fixed(&keybinding($text, vec), 24);
-                }
+                let listener2 = Arc::clone(&listener);
+                frame.fixed(&keybinding_row(&listener, &mut buttons, $text, vec![
+                    Box::new(move |edit| { 
+                        let mut keybindings = listener2.minecraft.keybindings.lock().unwrap();
+                        if let Edit::Set(key) = edit { keybindings.$ident = key }
+                        key_label(keybindings.$ident)
+                    })
+                ]), 24)
             }
         };
         ($text: expr, $($ident: ident),*) => {
             {
-                let listener = Arc::clone(&listener);
-                frame.fixed(&keybinding($text, vec![
+                let listener2 = Arc::clone(&listener);
+                frame.fixed(&keybinding_row(&listener, &mut buttons, $text, vec![
                     $(
-                        Box::new(move |set| { 
-                            let mut keybindings = listener.minecraft.keybindings.lock().unwrap();
-                            if let Some(key) = set { keybindings.$ident = key }
-                            keybindings.$ident
+                        Box::new(move |edit| { 
+                            let mut keybindings = listener2.minecraft.keybindings.lock().unwrap();
+                            if let Edit::Set(Some(binding)) = edit { keybindings.$ident = binding }
+                            key_label(Some(keybindings.$ident))
                         })
                     )*
                 ]), 24)
@@ -279,11 +584,129 @@ pub fn keybindings(frame: MenuFrame, listener: Arc<Listener>) {
     }
 
     keybindings!("Başlat", start);
+
+    fn activation_index(activation: Activation) -> i32 {
+        match activation {
+            Activation::Hold => 0,
+            Activation::Toggle => 1,
+            Activation::Timed { .. } => 2,
+        }
+    }
+
+    let mut row = group::Flex::default();
+    let label = frame::Frame::default().with_label("Başlatma modu");
+    let mut mode = menu::Choice::default();
+    row.fixed(&label, label.measure_label().0 + 8);
+    row.end();
+    frame.fixed(&row, 24);
+    mode.set_frame(enums::FrameType::FlatBox);
+    mode.set_color(Theme::BG_2);
+    mode.add_choice("Basılı tut|Aç/kapa|Süreli");
+    mode.set_value(activation_index(listener.minecraft.keybindings.lock().unwrap().activation));
+    let listener2 = Arc::clone(&listener);
+    mode.set_callback(move |mode| {
+        let current = listener2.minecraft.keybindings.lock().unwrap().activation;
+        let activation = match mode.value() {
+            0 => Activation::Hold,
+            1 => Activation::Toggle,
+            _ => {
+                let millis = match current { Activation::Timed { millis } => millis, _ => 3000 };
+                match dialog::input_default("Süre (ms):", &millis.to_string()).and_then(|millis| millis.parse().ok()) {
+                    Some(millis) => Activation::Timed { millis },
+                    None => current,
+                }
+            },
+        };
+        mode.set_value(activation_index(activation));
+        listener2.minecraft.keybindings.lock().unwrap().activation = activation;
+        listener2.save_settings();
+    });
+
     frame.fixed(&frame::Frame::default(), 4);
-    keybindings!("Kılıç eli", sword);
-    keybindings!("Olta eli", fishing_rod);
+    // phase slots are part of the settings
+    let len = listener.service.settings.lock().unwrap().phases.len();
+    for i in 0..len {
+        let listener2 = Arc::clone(&listener);
+        frame.fixed(&keybinding_row(&listener, &mut buttons, &format!("{}. aşama eli", i + 1)[..], vec![
+            Box::new(move |edit| {
+                let mut settings = listener2.service.settings.lock().unwrap();
+                match settings.phases.get_mut(i) {
+                    Some(phase) => {
+                        if let Some(key) = edit.slot() { phase.slot = key }
+                        key_label(Some(phase.slot.into()))
+                    },
+                    None => key_label(None),
+                }
+            })
+        ]), 24);
+    }
     frame.fixed(&frame::Frame::default(), 4);
-    keybindings!("Özel", [custom]);
+
+    fn fill_action(choice: &mut menu::Choice, action: &Action) {
+        choice.clear();
+        choice.add_choice("Kullan|Vur|Basılı tut|Çift kullan");
+        // sequences are only editable in the keybindings file
+        if let Action::Sequence(_) = action { choice.add_choice("Sıra"); }
+        choice.set_value(match action {
+            Action::Use => 0,
+            Action::Punch => 1,
+            Action::Hold { .. } => 2,
+            Action::DoubleUse => 3,
+            Action::Sequence(_) => 4,
+        });
+    }
+
+    let mut actions = Vec::new();
+    let len = listener.minecraft.keybindings.lock().unwrap().custom.len();
+    for x in 0..len {
+        let listener2 = Arc::clone(&listener);
+        let listener3 = Arc::clone(&listener);
+        let mut row = keybinding_row(&listener, &mut buttons, "", vec![
+            Box::new(move |edit| {
+                let mut keybindings = listener2.minecraft.keybindings.lock().unwrap();
+                if let Edit::Set(Some(binding)) = edit { keybindings.custom[x].hotkey = binding }
+                key_label(Some(keybindings.custom[x].hotkey))
+            }) as KeyEdit,
+            Box::new(move |edit| {
+                let mut keybindings = listener3.minecraft.keybindings.lock().unwrap();
+                if let Some(key) = edit.slot() { keybindings.custom[x].slot = key }
+                key_label(Some(keybindings.custom[x].slot.into()))
+            }),
+        ]);
+
+        let mut action = menu::Choice::default();
+        row.insert(&action, 0);
+        row.fixed(&action, 80);
+        action.set_frame(enums::FrameType::FlatBox);
+        action.set_color(Theme::BG_2);
+        fill_action(&mut action, &listener.minecraft.keybindings.lock().unwrap().custom[x].action);
+        let listener2 = Arc::clone(&listener);
+        action.set_callback(move |choice| {
+            let current = listener2.minecraft.keybindings.lock().unwrap().custom[x].action.clone();
+            let action = match choice.value() {
+                0 => Action::Use,
+                1 => Action::Punch,
+                2 => {
+                    let millis = match current { Action::Hold { millis } => millis, _ => 1600 };
+                    match dialog::input_default("Süre (ms):", &millis.to_string()).and_then(|millis| millis.parse().ok()) {
+                        Some(millis) => Action::Hold { millis },
+                        None => current,
+                    }
+                },
+                3 => Action::DoubleUse,
+                _ => current,
+            };
+            fill_action(choice, &action);
+            listener2.minecraft.keybindings.lock().unwrap().custom[x].action = action;
+            listener2.save_settings();
+        });
+
+        frame.fixed(&row, 24);
+        actions.push((x, action));
+    }
+    frame.fixed(&frame::Frame::default(), 4);
+    keybindings!("Sonraki profil", ?profile_next);
+    keybindings!("Önceki profil", ?profile_previous);
 
     let _ = frame::Frame::default();
     let mut f = group::Flex::default();
@@ -291,10 +714,11 @@ pub fn keybindings(frame: MenuFrame, listener: Arc<Listener>) {
     let mut reset = button::Button::default().with_label("Sıfırla");
     reset.set_callback(move |_| {
         listener.minecraft.reset_keybindings();
-        for (flex, btn, key) in &mut buttons {
-            btn.set_label(&format!("{:?}", (*key.lock().unwrap())(None))[..]);
-            flex.fixed(btn, btn.measure_label().0 + 16);
-            app::redraw();
+        refresh_key_labels(&mut buttons);
+        let keybindings = listener.minecraft.keybindings.lock().unwrap();
+        mode.set_value(activation_index(keybindings.activation));
+        for (x, action) in actions.iter_mut() {
+            if let Some(custom) = keybindings.custom.get(*x) { fill_action(action, &custom.action) }
         }
     });
     f.fixed(&reset, reset.measure_label().0 + 16);
@@ -307,6 +731,205 @@ pub fn keybindings(frame: MenuFrame, listener: Arc<Listener>) {
 
 
 
+pub fn profiles(frame: MenuFrame, listener: Arc<Listener>) {
+    type Action = fn(&Listener, &str) -> Result<(), String>;
+
+    fn fill_profiles(choice: &mut menu::Choice, listener: &Listener) {
+        choice.clear();
+        let active = listener.active_profile();
+        for (i, profile) in listener.profiles().iter().enumerate() {
+            choice.add_choice(&profile[..]);
+            if *profile == active { choice.set_value(i as i32); }
+        }
+    }
+
+    // hotkeys to other profiles are kept in keybindings of the active one
+    fn fill_hotkeys(frame: &mut group::Flex, listener: &Arc<Listener>) {
+        for i in (0..frame.children()).rev() {
+            app::delete_widget(frame.child(i).unwrap());
+        }
+
+        frame.begin();
+        let mut buttons = Vec::new();
+        let active = listener.active_profile();
+        for profile in listener.profiles().into_iter().filter(|profile| *profile != active) {
+            let listener2 = Arc::clone(listener);
+            let name = profile.clone();
+            let row = keybinding_row(listener, &mut buttons, &profile[..], vec![
+                Box::new(move |edit| {
+                    let mut keybindings = listener2.minecraft.keybindings.lock().unwrap();
+                    if let Edit::Set(key) = edit {
+                        keybindings.profile_select.retain(|(_, profile)| *profile != name);
+                        if let Some(key) = key { keybindings.profile_select.push((key, name.clone())) }
+                    }
+                    key_label(keybindings.profile_select.iter()
+                        .find(|(_, profile)| *profile == name)
+                        .map(|(key, _)| *key))
+                }) as KeyEdit
+            ]);
+            frame.fixed(&row, 24);
+        }
+        frame.end();
+        app::redraw();
+    }
+
+    fn export(listener: &Listener, active: &str) -> Result<(), String> {
+        let shared = listener.export_profile(active)?;
+        match dialog::choice2_default("Profil nasıl dışa aktarılsın?", "İptal", "Dosya", "Kod") {
+            Some(1) => {
+                let mut chooser = dialog::NativeFileChooser::new(dialog::NativeFileChooserType::BrowseSaveFile);
+                chooser.set_option(dialog::NativeFileChooserOptions::SaveAsConfirm);
+                chooser.set_preset_file(&format!("{}.{}", active, EXTENSION));
+                chooser.show();
+                let path = chooser.filename();
+                if path.as_os_str().is_empty() { return Ok(()) }
+                std::fs::write(path, shared.to_file()?).map_err(|err| err.to_string())
+            },
+            Some(2) => {
+                app::copy(&shared.to_code()?);
+                dialog::message_default("Profil kodu panoya kopyalandı.");
+                Ok(())
+            },
+            _ => Ok(()),
+        }
+    }
+
+    fn import(listener: &Listener, _: &str) -> Result<(), String> {
+        let text = match dialog::choice2_default("Profil nereden içe aktarılsın?", "İptal", "Dosya", "Kod") {
+            Some(1) => {
+                let mut chooser = dialog::NativeFileChooser::new(dialog::NativeFileChooserType::BrowseFile);
+                chooser.set_filter(&format!("*.{}", EXTENSION));
+                chooser.show();
+                let path = chooser.filename();
+                if path.as_os_str().is_empty() { return Ok(()) }
+                std::fs::read_to_string(path).map_err(|err| err.to_string())?
+            },
+            Some(2) => match dialog::input_default("Profil kodu:", "") {
+                Some(code) => code,
+                None => return Ok(()),
+            },
+            _ => return Ok(()),
+        };
+        let shared = SharedProfile::parse(&text)?;
+
+        let mut preview = format!(
+            "Profil: {}\nSürüm: {}\nAşama sayısı: {}\nBaşlatma tuşu: {}\nÖzel eşyalar: {}",
+            shared.name,
+            shared.app_version,
+            shared.settings.phases.len(),
+            shared.keybindings.start,
+            shared.keybindings.custom.len(),
+        );
+        for warning in shared.warnings() { preview += &format!("\n\nUyarı: {}", warning) }
+        if dialog::choice2_default(&preview, "İptal", "İçe aktar", "") != Some(1) { return Ok(()) }
+
+        let Some(name) = dialog::input_default("Profil adı:", &shared.name) else { return Ok(()) };
+        listener.import_profile(&shared, &name)?;
+        listener.set_active_profile(&name)
+    }
+
+    frame.begin();
+    frame.set_type(group::FlexType::Column);
+
+    let label = frame::Frame::default()
+        .with_label("Aktif profil")
+        .with_align(enums::Align::Left | enums::Align::Inside);
+    frame.fixed(&label, 16);
+
+    let mut choice = menu::Choice::default();
+    frame.fixed(&choice, 24);
+    choice.set_frame(enums::FrameType::FlatBox);
+    choice.set_color(Theme::BG_2);
+    fill_profiles(&mut choice, &listener);
+
+    frame.fixed(&frame::Frame::default(), 4);
+
+    let actions: [(&str, Action); 7] = [
+        ("Yeni", |listener, _| {
+            let Some(name) = dialog::input_default("Profil adı:", "") else { return Ok(()) };
+            listener.create_profile(&name)?;
+            listener.set_active_profile(&name)
+        }),
+        ("Kopyala", |listener, active| {
+            let Some(name) = dialog::input_default("Kopyanın adı:", &format!("{} kopya", active)) else { return Ok(()) };
+            listener.duplicate_profile(active, &name)?;
+            listener.set_active_profile(&name)
+        }),
+        ("Yeniden adlandır", |listener, active| {
+            let Some(name) = dialog::input_default("Yeni ad:", active) else { return Ok(()) };
+            listener.rename_profile(active, &name)
+        }),
+        ("Sil", |listener, active| {
+            let text = format!("\"{}\" profili silinsin mi?", active);
+            if dialog::choice2_default(&text, "İptal", "Sil", "") != Some(1) { return Ok(()) }
+            listener.delete_profile(active)
+        }),
+        ("Dosya biçimi", |listener, active| {
+            let format = match dialog::choice2_default("Profil dosyaları hangi biçimde saklansın?", "İptal", "JSON", "TOML") {
+                Some(1) => Format::Json,
+                Some(2) => Format::Toml,
+                _ => return Ok(()),
+            };
+            listener.convert_profile(active, format)
+        }),
+        ("Dışa aktar", export),
+        ("İçe aktar", import),
+    ];
+
+    let mut action_buttons = Vec::new();
+    for pair in actions.chunks(2) {
+        let row = group::Flex::default();
+        for (label, action) in pair {
+            let mut btn = button::Button::default().with_label(label);
+            format_button(&mut btn);
+            btn_cursor(&mut btn);
+            action_buttons.push((btn, *action));
+        }
+        row.end();
+        frame.fixed(&row, 24);
+    }
+
+    frame.fixed(&frame::Frame::default(), 4);
+    let label = frame::Frame::default()
+        .with_label("Profile geçiş kısayolları")
+        .with_align(enums::Align::Left | enums::Align::Inside);
+    frame.fixed(&label, 16);
+
+    let mut hotkeys = group::Flex::default();
+    hotkeys.set_type(group::FlexType::Column);
+    hotkeys.end();
+    fill_hotkeys(&mut hotkeys, &listener);
+
+    frame.end();
+
+    let listener2 = Arc::clone(&listener);
+    let mut hotkeys2 = hotkeys.clone();
+    choice.set_callback(move |choice| {
+        if let Some(name) = choice.choice() {
+            if let Err(err) = listener2.set_active_profile(&name) {
+                dialog::alert_default(&err);
+            }
+        }
+        fill_profiles(choice, &listener2);
+        fill_hotkeys(&mut hotkeys2, &listener2);
+    });
+
+    for (mut btn, action) in action_buttons {
+        let listener = Arc::clone(&listener);
+        let mut choice = choice.clone();
+        let mut hotkeys = hotkeys.clone();
+        btn.set_callback(move |_| {
+            if let Err(err) = action(&listener, &listener.active_profile()) {
+                dialog::alert_default(&err);
+            }
+            fill_profiles(&mut choice, &listener);
+            fill_hotkeys(&mut hotkeys, &listener);
+        });
+    }
+}
+
+
+
 pub fn metw() {
     let _ = webbrowser::open("https://metw.cc/a/pvp-macro");
 }
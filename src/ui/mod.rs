@@ -7,7 +7,7 @@ use fltk::{prelude::*, *};
 
 use theme::Theme;
 
-use crate::keyboard::Listener;
+use crate::keyboard::{Listener, LoadError, Fallback};
 use std::sync::{
     Arc,
     Mutex
@@ -20,13 +20,14 @@ pub struct UI {
     sidebar: Mutex<group::Flex>,
     root: Mutex<group::Flex>,
     current_menu: Mutex<Option<group::Flex>>,
+    current_kind: Mutex<Option<sidebar::Menu>>,
 }
 
 impl UI {
     pub fn new(listener: Arc<Listener>) -> Arc<Self> {
         let window = window::Window::default()
             .with_label("PvP Macro")
-            .with_size(248, 300);
+            .with_size(248, 320);
         window.end();
 
         let mut root = group::Flex::default();
@@ -50,6 +51,7 @@ impl UI {
             sidebar: Mutex::new(sidebar),
             root: Mutex::new(root),
             current_menu: Mutex::new(None),
+            current_kind: Mutex::new(None),
         })
     }
 
@@ -86,6 +88,30 @@ impl UI {
         self.init_sidebar();
         self.select_menu(&sidebar::Menu::Info);
 
+        // files can fail to load on startup, on every profile switch and
+        // when they are changed outside, and fail to save whenever edited;
+        // menus showing them are rebuilt
+        let ui = Arc::clone(self);
+        let mut generation = self.listener.generation();
+        app::add_timeout3(0.5, move |handle| {
+            let errors = ui.listener.take_load_errors();
+            if !errors.is_empty() {
+                dialog::alert_default(&load_errors_message(&errors)[..]);
+            }
+            let errors = ui.listener.take_save_errors();
+            if !errors.is_empty() {
+                dialog::alert_default(&format!("Değişiklikler kaydedilemedi.\n\n{}", errors.join("\n")));
+            }
+            if generation != ui.listener.generation() {
+                generation = ui.listener.generation();
+                let current = *ui.current_kind.lock().unwrap();
+                if let Some(menu @ (sidebar::Menu::Settings | sidebar::Menu::KeyBindings | sidebar::Menu::Profiles)) = current {
+                    ui.select_menu(&menu);
+                }
+            }
+            app::repeat_timeout3(0.5, handle);
+        });
+
         window.set_callback(|_| {
             if app::event() == enums::Event::Close {
                 app::quit()
@@ -97,3 +123,19 @@ impl UI {
         self.app.run().unwrap();
     }
 }
+
+fn load_errors_message(errors: &[LoadError]) -> String {
+    let mut message = String::from("Bazı dosyalar okunamadı.\n");
+    for error in errors {
+        message += &format!("\n{}\n", error);
+        match &error.fallback {
+            Fallback::Defaults { backup: Some(backup) } => {
+                message += &format!("Varsayılan ayarlar kullanıldı, dosyanın yedeği: {}\n", backup.display());
+            },
+            Fallback::Defaults { backup: None } =>
+                message += "Varsayılan ayarlar kullanıldı, dosya yedeklenemediği için üzerine yazılmayacak.\n",
+            Fallback::Kept => message += "Değişiklik uygulanmadı, önceki ayarlar kullanılıyor.\n",
+        }
+    }
+    message
+}
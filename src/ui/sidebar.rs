@@ -9,9 +9,9 @@ use fltk::{prelude::*, *};
 
 use std::sync::Arc;
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone, Copy)]
 pub enum Menu {
-    Run, Settings, KeyBindings, Info, Metw
+    Run, Settings, KeyBindings, Profiles, Info, Metw
 }
 
 enum MenuFn {
@@ -19,10 +19,11 @@ enum MenuFn {
     Macro (fn(crate::ui::menus::MenuFrame, Arc<crate::keyboard::Listener>) -> (), ),
     NoArg (fn() -> (), ),
 }
-static MENU_DATA: [(Menu, &str, MenuFn); 5] = [
+static MENU_DATA: [(Menu, &str, MenuFn); 6] = [
     (Menu::Run, "sidebar/run.svg", MenuFn::Macro(menus::run)),
     (Menu::Settings, "sidebar/settings.svg", MenuFn::Macro(menus::settings)),
     (Menu::KeyBindings, "sidebar/keybindings.svg", MenuFn::Macro(menus::keybindings)),
+    (Menu::Profiles, "sidebar/profiles.svg", MenuFn::Macro(menus::profiles)),
     (Menu::Info, "sidebar/info.svg", MenuFn::Standard(menus::info)),
     (Menu::Metw, "sidebar/metw.svg", MenuFn::NoArg(menus::metw)),
 ];
@@ -78,6 +79,7 @@ impl UI {
                 }
 
                 *self.current_menu.lock().unwrap() = Some(menu);
+                *self.current_kind.lock().unwrap() = Some(*menu2);
                 app::redraw();
                 break
             }